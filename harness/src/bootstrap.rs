@@ -0,0 +1,73 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub const REGION: &str = "eu-west-1";
+pub const FUNCTION_NAME: &str = "rotator";
+
+/// The `bootstrap` binary running as a Lambda function would, with its output collected.
+///
+/// The child is killed on drop, since it otherwise polls for events forever.
+pub struct Bootstrap {
+    child: Child,
+    logs: Arc<Mutex<Vec<String>>>,
+}
+
+impl Bootstrap {
+    /// Starts the binary with only the environment Lambda provides plus `env`, which may override it.
+    pub fn start(binary: &Path, env: &[(String, String)]) -> io::Result<Self> {
+        let mut command = Command::new(binary);
+        command.env_clear()
+            .env("AWS_LAMBDA_FUNCTION_NAME", FUNCTION_NAME)
+            .env("AWS_LAMBDA_FUNCTION_MEMORY_SIZE", "128")
+            .env("AWS_LAMBDA_FUNCTION_VERSION", "$LATEST")
+            .env("AWS_LAMBDA_LOG_GROUP_NAME", format!("/aws/lambda/{}", FUNCTION_NAME))
+            .env("AWS_LAMBDA_LOG_STREAM_NAME", "harness")
+            .env("AWS_REGION", REGION)
+            .env("AWS_ACCESS_KEY_ID", "AKIAFAKE")
+            .env("AWS_SECRET_ACCESS_KEY", "fake")
+            .env("AWS_EC2_METADATA_DISABLED", "true")
+            .env("RUST_LOG", "info")
+            .envs(env.iter().map(|(name, value)| (name, value)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let mut child = command.spawn()?;
+        let logs = Arc::new(Mutex::new(vec![]));
+
+        if let Some(stdout) = child.stdout.take() {
+            collect(stdout, logs.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            collect(stderr, logs.clone());
+        }
+
+        Ok(Bootstrap {
+            child,
+            logs,
+        })
+    }
+
+    /// Lines written to stdout and stderr so far.
+    pub fn logs(&self) -> Vec<String> {
+        self.logs.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Bootstrap {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn collect<R: Read + Send + 'static>(output: R, logs: Arc<Mutex<Vec<String>>>) {
+    thread::spawn(move || {
+        for line in BufReader::new(output).lines().map_while(Result::ok) {
+            logs.lock().unwrap().push(line);
+        }
+    });
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+/// An HTTP/1.1 request, with header names in lower case.
+#[derive(Clone, Debug, Default)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(|value| &value[..])
+    }
+
+    pub fn body_str(&self) -> &str {
+        std::str::from_utf8(&self.body).unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        Response {
+            status,
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+            body: body.into(),
+        }
+    }
+
+    pub fn json(status: u16, body: &serde_json::Value) -> Self {
+        Response::new(status, "application/json", body.to_string())
+    }
+
+    pub fn empty(status: u16) -> Self {
+        Response::new(status, "text/plain", vec![])
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+pub type Handler = Arc<dyn Fn(Request) -> Response + Send + Sync>;
+
+/// A blocking HTTP server on a local port, with a thread per connection so that handlers may block.
+///
+/// Only what the AWS SDK and the Lambda runtime client send is supported:
+/// `Content-Length` or chunked bodies and keep-alive connections.
+pub struct Server {
+    address: SocketAddr,
+}
+
+impl Server {
+    pub fn start(handler: Handler) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let handler = handler.clone();
+                thread::spawn(move || {
+                    if let Err(err) = serve(stream, handler) {
+                        if err.kind() != io::ErrorKind::UnexpectedEof {
+                            eprintln!("harness http connection error: {}", err);
+                        }
+                    }
+                });
+            }
+        });
+
+        Ok(Server { address })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+}
+
+fn serve(stream: TcpStream, handler: Handler) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    while let Some(request) = read_request(&mut reader)? {
+        let close = request.header("connection").map(|value| value.eq_ignore_ascii_case("close")).unwrap_or(false);
+        write_response(&mut writer, &handler(request))?;
+        if close {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end_matches(&['\r', '\n'][..]).to_string()))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads the next request, or None when the client closes the connection.
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<Request>> {
+    let request_line = match read_line(reader)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().ok_or_else(|| invalid("missing method"))?.to_string();
+    let path = parts.next().ok_or_else(|| invalid("missing path"))?.to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let line = read_line(reader)?.ok_or_else(|| invalid("connection closed in headers"))?;
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':').ok_or_else(|| invalid("malformed header"))?;
+        headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+    }
+
+    let chunked = headers.get("transfer-encoding").map(|value| value.eq_ignore_ascii_case("chunked")).unwrap_or(false);
+    let body = if chunked {
+        read_chunked(reader)?
+    } else {
+        let length: usize = headers.get("content-length").map(|value| value.parse()).transpose()
+            .map_err(|_| invalid("malformed content-length"))?
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        body
+    };
+
+    Ok(Some(Request {
+        method,
+        path,
+        headers,
+        body,
+    }))
+}
+
+fn read_chunked<R: BufRead>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut body = vec![];
+
+    loop {
+        let size_line = read_line(reader)?.ok_or_else(|| invalid("connection closed in chunked body"))?;
+        let size = usize::from_str_radix(size_line.split(';').next().unwrap_or_default().trim(), 16)
+            .map_err(|_| invalid("malformed chunk size"))?;
+
+        if size == 0 {
+            // trailers, then a blank line
+            while !read_line(reader)?.unwrap_or_default().is_empty() {}
+            return Ok(body);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        read_line(reader)?;
+    }
+}
+
+fn write_response<W: Write>(writer: &mut W, response: &Response) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {} {}\r\nContent-Length: {}\r\n", response.status, reason(response.status), response.body.len());
+    for (name, value) in response.headers.iter() {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    writer.write_all(head.as_bytes())?;
+    writer.write_all(&response.body)?;
+    writer.flush()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        404 => "Not Found",
+        409 => "Conflict",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_read_requests() {
+        let input = "POST /a HTTP/1.1\r\nContent-Length: 5\r\nX-Amz-Target: secretsmanager.DescribeSecret\r\n\r\nhello\
+            POST /b HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2;ext=1\r\nde\r\n0\r\n\r\n";
+        let mut reader = BufReader::new(input.as_bytes());
+
+        let first = read_request(&mut reader).unwrap().unwrap();
+        assert_eq!(first.path, "/a");
+        assert_eq!(first.header("X-Amz-Target"), Some("secretsmanager.DescribeSecret"));
+        assert_eq!(first.body_str(), "hello");
+
+        let second = read_request(&mut reader).unwrap().unwrap();
+        assert_eq!(second.path, "/b");
+        assert_eq!(second.body_str(), "abcde");
+
+        assert!(read_request(&mut reader).unwrap().is_none());
+    }
+}
@@ -0,0 +1,284 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use crate::http::{Request, Response, Server};
+
+const ACCOUNT: &str = "123456789012";
+const XMLNS: &str = "https://iam.amazonaws.com/doc/2010-05-08/";
+
+/// IAM allows two service specific credentials per user and service.
+const MAX_CREDENTIALS: usize = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FakeCredential {
+    pub id: String,
+    pub user_name: String,
+    pub service_name: String,
+    pub service_user_name: String,
+    pub password: String,
+    pub status: String,
+    pub create_date: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FakeUser {
+    pub name: String,
+    pub path: String,
+}
+
+#[derive(Default)]
+struct IamState {
+    users: Vec<FakeUser>,
+    credentials: Vec<FakeCredential>,
+    calls: Vec<String>,
+    counter: u64,
+}
+
+impl IamState {
+    fn user(&self, name: &str) -> Result<&FakeUser, Response> {
+        self.users.iter()
+            .find(|user| user.name == name)
+            .ok_or_else(|| error(404, "NoSuchEntity", &format!("The user with name {} cannot be found.", name)))
+    }
+
+    fn credential_mut(&mut self, id: &str) -> Result<&mut FakeCredential, Response> {
+        self.credentials.iter_mut()
+            .find(|cred| cred.id == id)
+            .ok_or_else(|| error(404, "NoSuchEntity", &format!("The Service Specific Credential with id {} cannot be found.", id)))
+    }
+
+    /// Ids, passwords and creation dates are deterministic and increase with each call.
+    fn next(&mut self) -> u64 {
+        self.counter += 1;
+        self.counter
+    }
+}
+
+/// IAM's query API, served locally, with users and their service specific credentials kept in memory.
+///
+/// Point the rotator at it with `AWS_ENDPOINT_URL_IAM`.
+#[derive(Clone)]
+pub struct FakeIam {
+    state: Arc<Mutex<IamState>>,
+    url: String,
+}
+
+impl FakeIam {
+    pub fn start() -> io::Result<Self> {
+        let state = Arc::new(Mutex::new(IamState::default()));
+        let handler_state = state.clone();
+        let server = Server::start(Arc::new(move |request| handle(&handler_state, request)))?;
+
+        Ok(FakeIam {
+            state,
+            url: server.url(),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn add_user(&self, name: &str, path: &str) {
+        self.state.lock().unwrap().users.push(FakeUser {
+            name: name.to_string(),
+            path: path.to_string(),
+        });
+    }
+
+    /// Adds a credential as if it had been created outside the rotator, returning its id.
+    pub fn add_credential(&self, user_name: &str, service_name: &str) -> String {
+        let mut state = self.state.lock().unwrap();
+        let cred = new_credential(&mut state, user_name, service_name);
+        state.credentials.push(cred.clone());
+        cred.id
+    }
+
+    pub fn credentials(&self) -> Vec<FakeCredential> {
+        self.state.lock().unwrap().credentials.clone()
+    }
+
+    /// The actions called so far, e.g. `ListServiceSpecificCredentials`.
+    pub fn calls(&self) -> Vec<String> {
+        self.state.lock().unwrap().calls.clone()
+    }
+}
+
+fn new_credential(state: &mut IamState, user_name: &str, service_name: &str) -> FakeCredential {
+    let n = state.next();
+
+    FakeCredential {
+        id: format!("ACCAFAKE{:013}", n),
+        user_name: user_name.to_string(),
+        service_name: service_name.to_string(),
+        service_user_name: format!("{}-at-{}", user_name, ACCOUNT),
+        password: format!("password-{}", n),
+        status: "Active".to_string(),
+        create_date: create_date(n),
+    }
+}
+
+fn create_date(n: u64) -> String {
+    format!("2026-01-01T00:{:02}:{:02}Z", (n / 60) % 60, n % 60)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn element(name: &str, value: &str) -> String {
+    format!("<{}>{}</{}>", name, escape(value), name)
+}
+
+fn ok(action: &str, result: &str) -> Response {
+    let body = format!(
+        "<{action}Response xmlns=\"{xmlns}\"><{action}Result>{result}</{action}Result><ResponseMetadata><RequestId>fake</RequestId></ResponseMetadata></{action}Response>",
+        action = action, xmlns = XMLNS, result = result);
+
+    Response::new(200, "text/xml", body)
+}
+
+fn error(status: u16, code: &str, message: &str) -> Response {
+    let body = format!(
+        "<ErrorResponse xmlns=\"{}\"><Error><Type>Sender</Type>{}{}</Error><RequestId>fake</RequestId></ErrorResponse>",
+        XMLNS, element("Code", code), element("Message", message));
+
+    Response::new(status, "text/xml", body)
+}
+
+fn credential_xml(cred: &FakeCredential, with_password: bool) -> String {
+    let mut xml = String::new();
+    xml.push_str(&element("UserName", &cred.user_name));
+    xml.push_str(&element("Status", &cred.status));
+    xml.push_str(&element("ServiceUserName", &cred.service_user_name));
+    xml.push_str(&element("CreateDate", &cred.create_date));
+    xml.push_str(&element("ServiceSpecificCredentialId", &cred.id));
+    xml.push_str(&element("ServiceName", &cred.service_name));
+    if with_password {
+        xml.push_str(&element("ServicePassword", &cred.password));
+    }
+    xml
+}
+
+fn handle(state: &Mutex<IamState>, request: Request) -> Response {
+    let params: Vec<(String, String)> = form_urlencoded::parse(&request.body).into_owned().collect();
+    let param = |name: &str| params.iter().find(|(key, _)| key == name).map(|(_, value)| &value[..]);
+
+    let action = param("Action").unwrap_or_default().to_string();
+    let user_name = param("UserName").unwrap_or_default();
+
+    let mut state = state.lock().unwrap();
+    state.calls.push(action.to_string());
+
+    let result = match &action[..] {
+        "GetUser" => get_user(&state, user_name),
+        "ListServiceSpecificCredentials" => list_credentials(&state, user_name, param("ServiceName")),
+        "CreateServiceSpecificCredential" => create_credential(&mut state, user_name, param("ServiceName").unwrap_or_default()),
+        "ResetServiceSpecificCredential" => reset_credential(&mut state, param("ServiceSpecificCredentialId").unwrap_or_default()),
+        "UpdateServiceSpecificCredential" => update_credential(&mut state, param("ServiceSpecificCredentialId").unwrap_or_default(), param("Status").unwrap_or_default()),
+        _ => Err(error(400, "InvalidAction", &format!("the fake doesn't support {}", action))),
+    };
+
+    result.unwrap_or_else(|response| response)
+}
+
+fn get_user(state: &IamState, user_name: &str) -> Result<Response, Response> {
+    let user = state.user(user_name)?;
+
+    let xml = format!("<User>{}{}{}{}{}</User>",
+        element("Path", &user.path),
+        element("UserName", &user.name),
+        element("UserId", "AIDAFAKE"),
+        element("Arn", &format!("arn:aws:iam::{}:user{}{}", ACCOUNT, user.path, user.name)),
+        element("CreateDate", &create_date(0)));
+
+    Ok(ok("GetUser", &xml))
+}
+
+fn list_credentials(state: &IamState, user_name: &str, service_name: Option<&str>) -> Result<Response, Response> {
+    state.user(user_name)?;
+
+    let members: String = state.credentials.iter()
+        .filter(|cred| cred.user_name == user_name && service_name.map(|name| cred.service_name == name).unwrap_or(true))
+        .map(|cred| format!("<member>{}</member>", credential_xml(cred, false)))
+        .collect();
+
+    Ok(ok("ListServiceSpecificCredentials", &format!("<ServiceSpecificCredentials>{}</ServiceSpecificCredentials>", members)))
+}
+
+fn create_credential(state: &mut IamState, user_name: &str, service_name: &str) -> Result<Response, Response> {
+    state.user(user_name)?;
+
+    let existing = state.credentials.iter()
+        .filter(|cred| cred.user_name == user_name && cred.service_name == service_name)
+        .count();
+    if existing >= MAX_CREDENTIALS {
+        return Err(error(409, "LimitExceeded", "Cannot exceed quota for ServiceSpecificCredentialsPerUserPerService: 2"));
+    }
+
+    let cred = new_credential(state, user_name, service_name);
+    state.credentials.push(cred.clone());
+
+    Ok(ok("CreateServiceSpecificCredential", &format!("<ServiceSpecificCredential>{}</ServiceSpecificCredential>", credential_xml(&cred, true))))
+}
+
+fn reset_credential(state: &mut IamState, id: &str) -> Result<Response, Response> {
+    let n = state.next();
+    let cred = state.credential_mut(id)?;
+    cred.password = format!("password-{}", n);
+
+    Ok(ok("ResetServiceSpecificCredential", &format!("<ServiceSpecificCredential>{}</ServiceSpecificCredential>", credential_xml(cred, true))))
+}
+
+fn update_credential(state: &mut IamState, id: &str, status: &str) -> Result<Response, Response> {
+    let cred = state.credential_mut(id)?;
+    cred.status = status.to_string();
+
+    Ok(ok("UpdateServiceSpecificCredential", ""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(iam: &FakeIam, body: &str) -> (u16, String) {
+        let request = Request {
+            method: "POST".to_string(),
+            path: "/".to_string(),
+            body: body.as_bytes().to_vec(),
+            ..Default::default()
+        };
+        let response = handle(&iam.state, request);
+
+        (response.status, String::from_utf8(response.body).unwrap())
+    }
+
+    #[test]
+    pub fn test_credentials() {
+        let iam = FakeIam::start().unwrap();
+        iam.add_user("bob", "/");
+        let existing = iam.add_credential("bob", "codecommit.amazonaws.com");
+
+        let (status, body) = call(&iam, "Action=CreateServiceSpecificCredential&Version=2010-05-08&UserName=bob&ServiceName=codecommit.amazonaws.com");
+        assert_eq!(status, 200);
+        assert!(body.contains("<ServicePassword>password-2</ServicePassword>"));
+
+        let (status, body) = call(&iam, "Action=CreateServiceSpecificCredential&Version=2010-05-08&UserName=bob&ServiceName=codecommit.amazonaws.com");
+        assert_eq!(status, 409);
+        assert!(body.contains("<Code>LimitExceeded</Code>"));
+
+        call(&iam, &format!("Action=ResetServiceSpecificCredential&Version=2010-05-08&UserName=bob&ServiceSpecificCredentialId={}", existing));
+        assert_eq!(iam.credentials()[0].password, "password-3");
+
+        let (_, body) = call(&iam, "Action=ListServiceSpecificCredentials&Version=2010-05-08&UserName=bob&ServiceName=codecommit.amazonaws.com");
+        assert_eq!(body.matches("<member>").count(), 2);
+        assert!(!body.contains("ServicePassword"));
+
+        let (status, body) = call(&iam, "Action=GetUser&Version=2010-05-08&UserName=alice");
+        assert_eq!(status, 404);
+        assert!(body.contains("<Code>NoSuchEntity</Code>"));
+    }
+}
@@ -0,0 +1,66 @@
+//! Runs the rotator's `bootstrap` binary as Lambda would, for end-to-end tests.
+//!
+//! The binary polls a local Lambda Runtime API for `RotatorEvent`s and calls local fakes of
+//! Secrets Manager and IAM through the SDK's endpoint overrides, so `main()` runs unchanged.
+
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use serde_json::Value;
+
+pub mod http;
+pub mod iam;
+pub mod runtime;
+pub mod secretsmanager;
+mod bootstrap;
+
+pub use bootstrap::{Bootstrap, FUNCTION_NAME, REGION};
+pub use iam::FakeIam;
+pub use runtime::{InvocationResult, RuntimeApi};
+pub use secretsmanager::FakeSecretsManager;
+
+/// The rotator's Lambda timeout, which bounds each invocation.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The bootstrap binary connected to a runtime API and fakes of the services it calls.
+pub struct Harness {
+    pub runtime: RuntimeApi,
+    pub secrets_manager: FakeSecretsManager,
+    pub iam: FakeIam,
+    bootstrap: Bootstrap,
+}
+
+impl Harness {
+    /// Starts the binary, with `env` added to the function's environment, e.g. `ROTATOR_` settings.
+    pub fn start(binary: &Path, env: &[(&str, &str)]) -> io::Result<Self> {
+        let runtime = RuntimeApi::start()?;
+        let secrets_manager = FakeSecretsManager::start()?;
+        let iam = FakeIam::start()?;
+
+        let mut function_env = vec![
+            ("AWS_LAMBDA_RUNTIME_API".to_string(), runtime.address().to_string()),
+            ("AWS_ENDPOINT_URL_SECRETS_MANAGER".to_string(), secrets_manager.url().to_string()),
+            ("AWS_ENDPOINT_URL_IAM".to_string(), iam.url().to_string()),
+        ];
+        function_env.extend(env.iter().map(|(name, value)| (name.to_string(), value.to_string())));
+
+        let bootstrap = Bootstrap::start(binary, &function_env)?;
+
+        Ok(Harness {
+            runtime,
+            secrets_manager,
+            iam,
+            bootstrap,
+        })
+    }
+
+    /// Invokes the function with the event, returning None if it failed to start or timed out.
+    pub fn invoke(&self, event: Value) -> Option<InvocationResult> {
+        self.runtime.invoke(event, DEFAULT_TIMEOUT)
+    }
+
+    /// The function's log lines so far, useful when an assertion fails.
+    pub fn logs(&self) -> Vec<String> {
+        self.bootstrap.logs()
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde_json::Value;
+use crate::http::{Request, Response, Server};
+
+const INVOCATION_PREFIX: &str = "/2018-06-01/runtime/invocation/";
+const INIT_ERROR_PATH: &str = "/2018-06-01/runtime/init/error";
+const FUNCTION_ARN: &str = "arn:aws:lambda:eu-west-1:123456789012:function:rotator";
+
+/// An event waiting to be fetched by the function.
+#[derive(Clone, Debug)]
+struct Invocation {
+    request_id: String,
+    payload: Value,
+    deadline: SystemTime,
+}
+
+/// What the function posted for an invocation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InvocationResult {
+    Response(Value),
+    /// The function's error, with `errorType` and `errorMessage` fields.
+    Error(Value),
+}
+
+impl InvocationResult {
+    pub fn response(self) -> Option<Value> {
+        match self {
+            InvocationResult::Response(value) => Some(value),
+            InvocationResult::Error(_) => None,
+        }
+    }
+
+    /// The `errorType` of an error, e.g. `NOT_SET_AS_PENDING`.
+    pub fn error_type(&self) -> Option<&str> {
+        match self {
+            InvocationResult::Error(error) => error["errorType"].as_str(),
+            InvocationResult::Response(_) => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct RuntimeState {
+    pending: VecDeque<Invocation>,
+    results: HashMap<String, InvocationResult>,
+    init_error: Option<Value>,
+    next_request_id: u64,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<RuntimeState>,
+    changed: Condvar,
+}
+
+/// The Lambda Runtime API that a custom runtime polls for events, served locally.
+///
+/// `/runtime/invocation/next` blocks until `invoke` queues an event,
+/// and the function's response or error is returned from `invoke`.
+pub struct RuntimeApi {
+    shared: Arc<Shared>,
+    server: Server,
+}
+
+impl RuntimeApi {
+    pub fn start() -> io::Result<Self> {
+        let shared = Arc::new(Shared::default());
+        let handler_shared = shared.clone();
+        let server = Server::start(Arc::new(move |request| handle(&handler_shared, request)))?;
+
+        Ok(RuntimeApi {
+            shared,
+            server,
+        })
+    }
+
+    /// The value for `AWS_LAMBDA_RUNTIME_API`.
+    pub fn address(&self) -> SocketAddr {
+        self.server.address()
+    }
+
+    /// Queues the event and waits for the function to respond to it.
+    ///
+    /// `timeout` is the function timeout, which the function sees as its deadline.
+    /// Returns None if the function didn't respond in time or failed to start.
+    pub fn invoke(&self, payload: Value, timeout: Duration) -> Option<InvocationResult> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        state.next_request_id += 1;
+        let request_id = format!("harness-{}", state.next_request_id);
+        state.pending.push_back(Invocation {
+            request_id: request_id.to_string(),
+            payload,
+            deadline: SystemTime::now() + timeout,
+        });
+        self.shared.changed.notify_all();
+
+        // allow for the time the function takes to fetch the event and post its result
+        let give_up = Instant::now() + timeout + Duration::from_secs(5);
+        loop {
+            if let Some(result) = state.results.remove(&request_id) {
+                return Some(result);
+            }
+            if state.init_error.is_some() {
+                return None;
+            }
+
+            let now = Instant::now();
+            if now >= give_up {
+                return None;
+            }
+            state = self.shared.changed.wait_timeout(state, give_up - now).unwrap().0;
+        }
+    }
+
+    /// The error the function reported if it failed to initialise.
+    pub fn init_error(&self) -> Option<Value> {
+        self.shared.state.lock().unwrap().init_error.clone()
+    }
+}
+
+fn handle(shared: &Shared, request: Request) -> Response {
+    if request.method == "GET" && request.path == format!("{}next", INVOCATION_PREFIX) {
+        return next_invocation(shared);
+    }
+
+    if request.method != "POST" {
+        return not_found(&request);
+    }
+
+    let body: Value = serde_json::from_slice(&request.body).unwrap_or(Value::Null);
+
+    if request.path == INIT_ERROR_PATH {
+        let mut state = shared.state.lock().unwrap();
+        state.init_error = Some(body);
+        shared.changed.notify_all();
+        return Response::json(202, &serde_json::json!({"status": "OK"}));
+    }
+
+    let rest = match request.path.strip_prefix(INVOCATION_PREFIX) {
+        Some(rest) => rest,
+        None => return not_found(&request),
+    };
+
+    let (request_id, result) = match rest.split_once('/') {
+        Some((request_id, "response")) => (request_id, InvocationResult::Response(body)),
+        Some((request_id, "error")) => (request_id, InvocationResult::Error(body)),
+        _ => return not_found(&request),
+    };
+
+    let mut state = shared.state.lock().unwrap();
+    state.results.insert(request_id.to_string(), result);
+    shared.changed.notify_all();
+
+    Response::json(202, &serde_json::json!({"status": "OK"}))
+}
+
+/// Long polls for the next event, as the real API does.
+fn next_invocation(shared: &Shared) -> Response {
+    let mut state = shared.state.lock().unwrap();
+
+    let invocation = loop {
+        if let Some(invocation) = state.pending.pop_front() {
+            break invocation;
+        }
+        state = shared.changed.wait(state).unwrap();
+    };
+
+    let deadline_ms = invocation.deadline.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+
+    Response::json(200, &invocation.payload)
+        .with_header("Lambda-Runtime-Aws-Request-Id", &invocation.request_id)
+        .with_header("Lambda-Runtime-Deadline-Ms", &deadline_ms.to_string())
+        .with_header("Lambda-Runtime-Invoked-Function-Arn", FUNCTION_ARN)
+        .with_header("Lambda-Runtime-Trace-Id", &format!("Root=1-00000000-{}", invocation.request_id))
+}
+
+fn not_found(request: &Request) -> Response {
+    Response::json(404, &serde_json::json!({
+        "errorType": "NotFound",
+        "errorMessage": format!("unknown runtime api route {} {}", request.method, request.path),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn next(api: &RuntimeApi) -> (String, Value) {
+        let request = Request {
+            method: "GET".to_string(),
+            path: format!("{}next", INVOCATION_PREFIX),
+            ..Default::default()
+        };
+        let response = handle(&api.shared, request);
+        let request_id = response.headers.iter()
+            .find(|(name, _)| name == "Lambda-Runtime-Aws-Request-Id")
+            .map(|(_, value)| value.to_string())
+            .expect("missing request id");
+
+        (request_id, serde_json::from_slice(&response.body).expect("invalid event"))
+    }
+
+    fn post(api: &RuntimeApi, path: &str, body: &str) -> u16 {
+        let request = Request {
+            method: "POST".to_string(),
+            path: path.to_string(),
+            body: body.as_bytes().to_vec(),
+            ..Default::default()
+        };
+        handle(&api.shared, request).status
+    }
+
+    #[test]
+    pub fn test_invoke() {
+        let api = Arc::new(RuntimeApi::start().unwrap());
+
+        let invoker = {
+            let api = api.clone();
+            thread::spawn(move || api.invoke(serde_json::json!({"HealthCheck": true}), Duration::from_secs(5)))
+        };
+
+        let (request_id, event) = next(&api);
+        assert_eq!(event, serde_json::json!({"HealthCheck": true}));
+        assert_eq!(post(&api, &format!("{}{}/response", INVOCATION_PREFIX, request_id), r#"{"healthy": true}"#), 202);
+        assert_eq!(invoker.join().unwrap(), Some(InvocationResult::Response(serde_json::json!({"healthy": true}))));
+
+        let invoker = {
+            let api = api.clone();
+            thread::spawn(move || api.invoke(serde_json::json!({}), Duration::from_secs(5)))
+        };
+
+        let (request_id, _) = next(&api);
+        post(&api, &format!("{}{}/error", INVOCATION_PREFIX, request_id), r#"{"errorType": "INVALID_EVENT", "errorMessage": "unrecognised event"}"#);
+        assert_eq!(invoker.join().unwrap().unwrap().error_type(), Some("INVALID_EVENT"));
+
+        assert_eq!(post(&api, "/2018-06-01/runtime/unknown", ""), 404);
+    }
+
+    #[test]
+    pub fn test_init_error() {
+        let api = RuntimeApi::start().unwrap();
+
+        post(&api, INIT_ERROR_PATH, r#"{"errorType": "Runtime.Unknown", "errorMessage": "missing env var"}"#);
+
+        assert_eq!(api.invoke(Value::Null, Duration::from_secs(5)), None);
+        assert_eq!(api.init_error().unwrap()["errorMessage"], "missing env var");
+    }
+}
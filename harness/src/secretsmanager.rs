@@ -0,0 +1,401 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use serde_json::{json, Map, Value};
+use crate::http::{Request, Response, Server};
+
+pub const AWSCURRENT: &str = "AWSCURRENT";
+pub const AWSPENDING: &str = "AWSPENDING";
+pub const AWSPREVIOUS: &str = "AWSPREVIOUS";
+
+const REGION: &str = "eu-west-1";
+const ACCOUNT: &str = "123456789012";
+const TARGET_PREFIX: &str = "secretsmanager.";
+
+/// One version of a fake secret. `value` is None until PutSecretValue gives it one,
+/// as for the version Secrets Manager creates when a rotation starts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FakeVersion {
+    pub id: String,
+    pub stages: Vec<String>,
+    /// The value as sent on the wire: a SecretString, or a base64 encoded SecretBinary.
+    pub value: Option<FakeValue>,
+    pub created_date: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FakeValue {
+    String(String),
+    Binary(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FakeSecret {
+    pub name: String,
+    pub arn: String,
+    pub rotation_enabled: bool,
+    pub primary_region: Option<String>,
+    pub tags: Vec<(String, String)>,
+    pub versions: Vec<FakeVersion>,
+}
+
+impl FakeSecret {
+    pub fn version(&self, id: &str) -> Option<&FakeVersion> {
+        self.versions.iter().find(|version| version.id == id)
+    }
+
+    pub fn version_with_stage(&self, stage: &str) -> Option<&FakeVersion> {
+        self.versions.iter().find(|version| version.stages.iter().any(|s| s == stage))
+    }
+
+    /// The SecretString of the version with the stage.
+    pub fn string_value(&self, stage: &str) -> Option<&str> {
+        match self.version_with_stage(stage)?.value.as_ref()? {
+            FakeValue::String(value) => Some(value),
+            FakeValue::Binary(_) => None,
+        }
+    }
+
+    /// Moves the stage to the version. Secrets Manager moves AWSPREVIOUS along with AWSCURRENT.
+    fn move_stage(&mut self, stage: &str, to_version: &str) {
+        if stage == AWSCURRENT {
+            if let Some(current) = self.version_with_stage(AWSCURRENT).map(|version| version.id.to_string()) {
+                if current != to_version {
+                    self.move_stage(AWSPREVIOUS, &current);
+                }
+            }
+        }
+
+        for version in self.versions.iter_mut() {
+            version.stages.retain(|s| s != stage);
+            if version.id == to_version {
+                version.stages.push(stage.to_string());
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct SmState {
+    secrets: Vec<FakeSecret>,
+    calls: Vec<String>,
+    clock: f64,
+}
+
+impl SmState {
+    fn secret_mut(&mut self, secret_id: &str) -> Result<&mut FakeSecret, Response> {
+        self.secrets.iter_mut()
+            .find(|secret| secret.name == secret_id || secret.arn == secret_id)
+            .ok_or_else(|| error("ResourceNotFoundException", &format!("Secrets Manager can't find the specified secret {}.", secret_id)))
+    }
+
+    fn tick(&mut self) -> f64 {
+        self.clock += 1.0;
+        1_767_225_600.0 + self.clock
+    }
+}
+
+/// Secrets Manager's JSON API, served locally, with the secrets kept in memory.
+///
+/// Supports the calls the rotator makes during rotation. Point the rotator at it with `AWS_ENDPOINT_URL_SECRETS_MANAGER`.
+#[derive(Clone)]
+pub struct FakeSecretsManager {
+    state: Arc<Mutex<SmState>>,
+    url: String,
+}
+
+impl FakeSecretsManager {
+    pub fn start() -> io::Result<Self> {
+        let state = Arc::new(Mutex::new(SmState::default()));
+        let handler_state = state.clone();
+        let server = Server::start(Arc::new(move |request| handle(&handler_state, request)))?;
+
+        Ok(FakeSecretsManager {
+            state,
+            url: server.url(),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Creates a secret with rotation enabled, whose value, if any, is version `initial` and AWSCURRENT.
+    pub fn create_secret(&self, name: &str, value: Option<&str>, tags: &[(&str, &str)]) -> String {
+        let mut state = self.state.lock().unwrap();
+        let created_date = state.tick();
+        let arn = format!("arn:aws:secretsmanager:{}:{}:secret:{}-AbCdEf", REGION, ACCOUNT, name);
+
+        state.secrets.push(FakeSecret {
+            name: name.to_string(),
+            arn: arn.to_string(),
+            rotation_enabled: true,
+            primary_region: None,
+            tags: tags.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+            versions: value.map(|value| FakeVersion {
+                id: "initial".to_string(),
+                stages: vec![AWSCURRENT.to_string()],
+                value: Some(FakeValue::String(value.to_string())),
+                created_date,
+            }).into_iter().collect(),
+        });
+
+        arn
+    }
+
+    /// Changes a secret, e.g. to disable rotation or make it a replica.
+    pub fn update_secret<F: FnOnce(&mut FakeSecret)>(&self, secret_id: &str, f: F) {
+        let mut state = self.state.lock().unwrap();
+        f(state.secret_mut(secret_id).expect("no such secret"));
+    }
+
+    /// Adds the AWSPENDING version that Secrets Manager creates, without a value, before invoking createSecret.
+    pub fn start_rotation(&self, secret_id: &str, token: &str) {
+        let mut state = self.state.lock().unwrap();
+        let created_date = state.tick();
+        let secret = state.secret_mut(secret_id).expect("no such secret");
+
+        secret.versions.push(FakeVersion {
+            id: token.to_string(),
+            stages: vec![],
+            value: None,
+            created_date,
+        });
+        secret.move_stage(AWSPENDING, token);
+    }
+
+    pub fn secret(&self, secret_id: &str) -> Option<FakeSecret> {
+        let mut state = self.state.lock().unwrap();
+        state.secret_mut(secret_id).ok().cloned()
+    }
+
+    /// The API calls made so far, e.g. `DescribeSecret`.
+    pub fn calls(&self) -> Vec<String> {
+        self.state.lock().unwrap().calls.clone()
+    }
+}
+
+fn error(error_type: &str, message: &str) -> Response {
+    Response::json(400, &json!({"__type": error_type, "message": message}))
+        .with_header("x-amzn-ErrorType", error_type)
+}
+
+fn ok(body: Value) -> Response {
+    Response::new(200, "application/x-amz-json-1.1", body.to_string())
+}
+
+fn handle(state: &Mutex<SmState>, request: Request) -> Response {
+    let operation = match request.header("x-amz-target").and_then(|target| target.strip_prefix(TARGET_PREFIX)) {
+        Some(operation) => operation.to_string(),
+        None => return error("UnknownOperationException", "missing X-Amz-Target"),
+    };
+    let input: Value = serde_json::from_slice(&request.body).unwrap_or_else(|_| json!({}));
+    let secret_id = input["SecretId"].as_str().unwrap_or_default();
+
+    let mut state = state.lock().unwrap();
+    state.calls.push(operation.to_string());
+
+    let result = match &operation[..] {
+        "DescribeSecret" => describe_secret(&mut state, secret_id),
+        "GetSecretValue" => get_secret_value(&mut state, secret_id, &input),
+        "PutSecretValue" => put_secret_value(&mut state, secret_id, &input),
+        "UpdateSecretVersionStage" => update_secret_version_stage(&mut state, secret_id, &input),
+        "TagResource" => tag_resource(&mut state, secret_id, &input),
+        "UntagResource" => untag_resource(&mut state, secret_id, &input),
+        _ => Err(error("UnknownOperationException", &format!("the fake doesn't support {}", operation))),
+    };
+
+    result.unwrap_or_else(|response| response)
+}
+
+fn describe_secret(state: &mut SmState, secret_id: &str) -> Result<Response, Response> {
+    let secret = state.secret_mut(secret_id)?;
+
+    let versions: Map<String, Value> = secret.versions.iter()
+        .filter(|version| !version.stages.is_empty())
+        .map(|version| (version.id.to_string(), json!(version.stages)))
+        .collect();
+    let tags: Vec<Value> = secret.tags.iter()
+        .map(|(key, value)| json!({"Key": key, "Value": value}))
+        .collect();
+
+    let mut output = json!({
+        "ARN": secret.arn,
+        "Name": secret.name,
+        "RotationEnabled": secret.rotation_enabled,
+        "VersionIdsToStages": versions,
+        "Tags": tags,
+    });
+    if let Some(ref primary_region) = secret.primary_region {
+        output["PrimaryRegion"] = json!(primary_region);
+    }
+
+    Ok(ok(output))
+}
+
+fn get_secret_value(state: &mut SmState, secret_id: &str, input: &Value) -> Result<Response, Response> {
+    let secret = state.secret_mut(secret_id)?;
+    let stage = input["VersionStage"].as_str();
+    let version_id = input["VersionId"].as_str();
+
+    let version = match version_id {
+        Some(id) => secret.version(id).filter(|version| stage.map(|s| version.stages.iter().any(|v| v == s)).unwrap_or(true)),
+        None => secret.version_with_stage(stage.unwrap_or(AWSCURRENT)),
+    };
+
+    let not_found = || error("ResourceNotFoundException", "Secrets Manager can't find the specified secret value for staging label or version id.");
+    let version = version.ok_or_else(not_found)?;
+    let value = version.value.as_ref().ok_or_else(not_found)?;
+
+    let mut output = json!({
+        "ARN": secret.arn,
+        "Name": secret.name,
+        "VersionId": version.id,
+        "VersionStages": version.stages,
+        "CreatedDate": version.created_date,
+    });
+    match value {
+        FakeValue::String(s) => output["SecretString"] = json!(s),
+        FakeValue::Binary(b) => output["SecretBinary"] = json!(b),
+    }
+
+    Ok(ok(output))
+}
+
+fn put_secret_value(state: &mut SmState, secret_id: &str, input: &Value) -> Result<Response, Response> {
+    let created_date = state.tick();
+    let secret = state.secret_mut(secret_id)?;
+    let token = input["ClientRequestToken"].as_str().unwrap_or_default().to_string();
+
+    let value = match (input["SecretString"].as_str(), input["SecretBinary"].as_str()) {
+        (Some(s), None) => FakeValue::String(s.to_string()),
+        (None, Some(b)) => FakeValue::Binary(b.to_string()),
+        _ => return Err(error("InvalidParameterException", "You must provide either SecretString or SecretBinary.")),
+    };
+    let stages: Vec<String> = match input["VersionStages"].as_array() {
+        Some(stages) => stages.iter().filter_map(|s| s.as_str()).map(str::to_string).collect(),
+        None => vec![AWSCURRENT.to_string()],
+    };
+
+    match secret.versions.iter_mut().find(|version| version.id == token) {
+        Some(version) => match version.value {
+            Some(ref existing) if *existing != value => {
+                return Err(error("ResourceExistsException", "A resource with the ID you requested already exists."));
+            },
+            _ => version.value = Some(value),
+        },
+        None => secret.versions.push(FakeVersion {
+            id: token.to_string(),
+            stages: vec![],
+            value: Some(value),
+            created_date,
+        }),
+    }
+
+    for stage in stages.iter() {
+        secret.move_stage(stage, &token);
+    }
+
+    Ok(ok(json!({
+        "ARN": secret.arn,
+        "Name": secret.name,
+        "VersionId": token,
+        "VersionStages": stages,
+    })))
+}
+
+fn update_secret_version_stage(state: &mut SmState, secret_id: &str, input: &Value) -> Result<Response, Response> {
+    let secret = state.secret_mut(secret_id)?;
+    let stage = input["VersionStage"].as_str().unwrap_or_default();
+    let move_to = input["MoveToVersionId"].as_str();
+    let remove_from = input["RemoveFromVersionId"].as_str();
+
+    let holder = secret.version_with_stage(stage).map(|version| version.id.to_string());
+
+    if let Some(ref holder) = holder {
+        if Some(&holder[..]) != move_to && Some(&holder[..]) != remove_from {
+            return Err(error("InvalidParameterException", &format!("The parameter RemoveFromVersionId can't be empty. Staging label {} is currently attached to version {}.", stage, holder)));
+        }
+    }
+
+    match move_to {
+        Some(move_to) => {
+            if secret.version(move_to).is_none() {
+                return Err(error("ResourceNotFoundException", &format!("Secrets Manager can't find the specified secret version {}.", move_to)));
+            }
+            secret.move_stage(stage, move_to);
+        },
+        None => {
+            for version in secret.versions.iter_mut().filter(|version| Some(&version.id[..]) == remove_from) {
+                version.stages.retain(|s| s != stage);
+            }
+        },
+    }
+
+    Ok(ok(json!({"ARN": secret.arn, "Name": secret.name})))
+}
+
+fn tag_resource(state: &mut SmState, secret_id: &str, input: &Value) -> Result<Response, Response> {
+    let secret = state.secret_mut(secret_id)?;
+
+    for tag in input["Tags"].as_array().into_iter().flatten() {
+        let key = tag["Key"].as_str().unwrap_or_default().to_string();
+        let value = tag["Value"].as_str().unwrap_or_default().to_string();
+        secret.tags.retain(|(k, _)| *k != key);
+        secret.tags.push((key, value));
+    }
+
+    Ok(ok(json!({})))
+}
+
+fn untag_resource(state: &mut SmState, secret_id: &str, input: &Value) -> Result<Response, Response> {
+    let secret = state.secret_mut(secret_id)?;
+    let keys: Vec<&str> = input["TagKeys"].as_array().into_iter().flatten().filter_map(|key| key.as_str()).collect();
+
+    secret.tags.retain(|(key, _)| !keys.contains(&&key[..]));
+
+    Ok(ok(json!({})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(sm: &FakeSecretsManager, operation: &str, input: Value) -> (u16, Value) {
+        let request = Request {
+            method: "POST".to_string(),
+            path: "/".to_string(),
+            headers: vec![("x-amz-target".to_string(), format!("{}{}", TARGET_PREFIX, operation))].into_iter().collect(),
+            body: input.to_string().into_bytes(),
+        };
+        let response = handle(&sm.state, request);
+
+        (response.status, serde_json::from_slice(&response.body).unwrap())
+    }
+
+    #[test]
+    pub fn test_rotation_stages() {
+        let sm = FakeSecretsManager::start().unwrap();
+        let arn = sm.create_secret("db", Some("old"), &[]);
+        sm.start_rotation("db", "v2");
+
+        let (status, output) = call(&sm, "GetSecretValue", json!({"SecretId": arn, "VersionId": "v2", "VersionStage": AWSPENDING}));
+        assert_eq!((status, output["__type"].as_str()), (400, Some("ResourceNotFoundException")));
+
+        let (status, _) = call(&sm, "PutSecretValue", json!({"SecretId": arn, "ClientRequestToken": "v2", "SecretString": "new", "VersionStages": [AWSPENDING]}));
+        assert_eq!(status, 200);
+
+        let (status, output) = call(&sm, "UpdateSecretVersionStage", json!({"SecretId": "db", "VersionStage": AWSCURRENT, "MoveToVersionId": "v2"}));
+        assert_eq!((status, output["__type"].as_str()), (400, Some("InvalidParameterException")));
+
+        call(&sm, "UpdateSecretVersionStage", json!({"SecretId": "db", "VersionStage": AWSCURRENT, "MoveToVersionId": "v2", "RemoveFromVersionId": "initial"}));
+
+        let secret = sm.secret("db").unwrap();
+        assert_eq!(secret.string_value(AWSCURRENT), Some("new"));
+        assert_eq!(secret.string_value(AWSPREVIOUS), Some("old"));
+        assert_eq!(secret.version("v2").unwrap().stages, vec![AWSPENDING.to_string(), AWSCURRENT.to_string()]);
+
+        let (_, output) = call(&sm, "DescribeSecret", json!({"SecretId": "db"}));
+        assert_eq!(output["VersionIdsToStages"]["initial"], json!([AWSPREVIOUS]));
+        assert_eq!(sm.calls(), vec!["GetSecretValue", "PutSecretValue", "UpdateSecretVersionStage", "UpdateSecretVersionStage", "DescribeSecret"]);
+    }
+}
@@ -35,6 +35,7 @@ export class InfraStack extends cdk.Stack {
         actions: [
           'iam:ListServiceSpecificCredentials',
           'iam:*ServiceSpecificCredential',
+          'iam:GetUser',
         ],
         resources: [
           '*'
@@ -47,6 +48,8 @@ export class InfraStack extends cdk.Stack {
           'secretsmanager:GetRandomPassword',
           'secretsmanager:GetSecretValue',
           'secretsmanager:PutSecretValue',
+          'secretsmanager:RotateSecret',
+          'secretsmanager:TagResource',
           'secretsmanager:UpdateSecretVersionStage'
         ],
         resources: [
@@ -58,6 +61,15 @@ export class InfraStack extends cdk.Stack {
           }
         }
       }),
+      new iam.PolicyStatement({
+        effect: iam.Effect.ALLOW,
+        actions: [
+          'secretsmanager:ListSecrets'
+        ],
+        resources: [
+          '*'
+        ]
+      }),
     );
 
     lambdaPolicy.attachToRole(rotator.role);
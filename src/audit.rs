@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_secretsmanager::types::Tag;
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use crate::{DYNAMODB_CLIENT, SM_CLIENT};
+use crate::metrics::time_api_call;
+use crate::value::{ResourceChange, SecretValue};
+
+const TAGS_ENVIRONMENT_VARIABLE: &str = "ROTATOR_AUDIT_TAGS";
+const TABLE_ENVIRONMENT_VARIABLE: &str = "ROTATOR_AUDIT_TABLE";
+const FILE_ENVIRONMENT_VARIABLE: &str = "ROTATOR_AUDIT_FILE";
+const LAMBDA_VERSION_ENVIRONMENT_VARIABLE: &str = "AWS_LAMBDA_FUNCTION_VERSION";
+
+/// Carries the changes createSecret made from createSecret to finishSecret, as `<token> <change> ...`.
+pub const PENDING_CHANGES_TAG: &str = "rotator:pendingChanges";
+
+/// What a rotation changed, written once finishSecret has made the new version current.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    pub secret_id: String,
+    pub version_id: String,
+    pub previous_version_id: Option<String>,
+    pub changes: Vec<ResourceChange>,
+    pub lambda_version: Option<String>,
+    /// When the pending version was created.
+    pub started_at: Option<String>,
+    pub finished_at: String,
+}
+
+impl AuditRecord {
+    /// The version finishSecret made current, replacing `previous_version_id`, and the changes createSecret made for it.
+    pub fn finished(secret_id: &str, version_id: &str, previous_version_id: Option<&str>, pending: &SecretValue, changes: Vec<ResourceChange>, finished_at: DateTime<Utc>) -> Self {
+        AuditRecord {
+            secret_id: secret_id.to_string(),
+            version_id: version_id.to_string(),
+            previous_version_id: previous_version_id.map(|id| id.to_string()),
+            changes,
+            lambda_version: std::env::var(LAMBDA_VERSION_ENVIRONMENT_VARIABLE).ok(),
+            started_at: pending.created_date
+                .and_then(|seconds| Utc.timestamp_millis_opt((seconds * 1000.0) as i64).single())
+                .map(timestamp),
+            finished_at: timestamp(finished_at),
+        }
+    }
+
+    /// The record as `rotator:lastRotation*` tags.
+    pub fn tags(&self) -> Vec<Tag> {
+        let mut tags = vec![
+            ("rotator:lastRotationVersion", Some(self.version_id.to_string())),
+            ("rotator:lastRotationPreviousVersion", self.previous_version_id.clone()),
+            ("rotator:lastRotationChanges", Some(change_words(&self.changes).join(" "))),
+            ("rotator:lastRotationLambdaVersion", self.lambda_version.clone()),
+            ("rotator:lastRotationStarted", self.started_at.clone()),
+            ("rotator:lastRotationFinished", Some(self.finished_at.to_string())),
+        ];
+        tags.retain(|(_, value)| value.is_some());
+
+        tags.into_iter()
+            .map(|(key, value)| Tag::builder()
+                .key(key)
+                .set_value(value)
+                .build())
+            .collect()
+    }
+}
+
+/// Millisecond timestamps, so that records of rotations finishing close together keep apart in a table keyed on `finishedAt`.
+fn timestamp(t: DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// The changes as words such as `reset:ID`. Tag values may not contain commas, so they're joined with spaces.
+fn change_words(changes: &[ResourceChange]) -> Vec<String> {
+    changes.iter()
+        .map(|change| match change {
+            ResourceChange::Created { credential_id } => format!("created:{}", credential_id),
+            ResourceChange::Reset { credential_id } => format!("reset:{}", credential_id),
+            ResourceChange::Activated { credential_id } => format!("activated:{}", credential_id),
+            ResourceChange::Deactivated { credential_id } => format!("deactivated:{}", credential_id),
+        })
+        .collect()
+}
+
+fn parse_change(word: &str) -> Option<ResourceChange> {
+    let (action, credential_id) = word.split_once(':')?;
+    let credential_id = credential_id.to_string();
+
+    match action {
+        "created" => Some(ResourceChange::Created { credential_id }),
+        "reset" => Some(ResourceChange::Reset { credential_id }),
+        "activated" => Some(ResourceChange::Activated { credential_id }),
+        "deactivated" => Some(ResourceChange::Deactivated { credential_id }),
+        _ => None,
+    }
+}
+
+/// The changes in the `rotator:pendingChanges` tag value, if createSecret wrote it for the token.
+pub fn pending_changes(value: &str, token: &str) -> Vec<ResourceChange> {
+    let mut words = value.split(' ');
+
+    if words.next() != Some(token) {
+        return vec![];
+    }
+
+    words.filter_map(parse_change).collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditError {
+    Tag {
+        message: String,
+    },
+    DynamoDb {
+        message: String,
+    },
+    File {
+        message: String,
+    },
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "audit error: {:?}", self)
+    }
+}
+
+impl std::error::Error for AuditError {
+}
+
+pub type AuditResult<T> = std::result::Result<T, AuditError>;
+
+#[async_trait]
+pub trait AuditSink: Send + Sync {
+    async fn write(&self, record: &AuditRecord) -> AuditResult<()>;
+}
+
+/// Tags the secret with the most recent rotation.
+pub struct TagAuditSink {
+}
+
+#[async_trait]
+impl AuditSink for TagAuditSink {
+    async fn write(&self, record: &AuditRecord) -> AuditResult<()> {
+        time_api_call("TagResource", SM_CLIENT.tag_resource()
+            .secret_id(&record.secret_id)
+            .set_tags(Some(record.tags()))
+            .send())
+            .await
+            .map_err(|err| AuditError::Tag {
+                message: format!("{:?}", err),
+            })
+            .map(|_| ())
+    }
+}
+
+/// Puts one item per record into a DynamoDB table keyed on `secretId` and `finishedAt`.
+pub struct DynamoDbAuditSink {
+    table_name: String,
+}
+
+#[async_trait]
+impl AuditSink for DynamoDbAuditSink {
+    async fn write(&self, record: &AuditRecord) -> AuditResult<()> {
+        let item = match attribute_value(&serde_json::to_value(record).unwrap_or(Value::Null)) {
+            AttributeValue::M(item) => item,
+            _ => return Err(AuditError::DynamoDb {
+                message: "audit record isn't an object".to_string(),
+            }),
+        };
+
+        time_api_call("PutItem", DYNAMODB_CLIENT.put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .send())
+            .await
+            .map_err(|err| AuditError::DynamoDb {
+                message: format!("{:?}", err),
+            })
+            .map(|_| ())
+    }
+}
+
+/// Appends each record as a JSON line to a local file, for tests and the CLI.
+pub struct FileAuditSink {
+    path: String,
+}
+
+#[async_trait]
+impl AuditSink for FileAuditSink {
+    async fn write(&self, record: &AuditRecord) -> AuditResult<()> {
+        let line = serde_json::to_string(record)
+            .map_err(|err| AuditError::File {
+                message: format!("{:?}", err),
+            })?;
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", line))
+            .map_err(|err| AuditError::File {
+                message: format!("{}: {:?}", self.path, err),
+            })
+    }
+}
+
+fn attribute_value(value: &Value) -> AttributeValue {
+    match value {
+        Value::Null => AttributeValue::Null(true),
+        Value::Bool(b) => AttributeValue::Bool(*b),
+        Value::Number(n) => AttributeValue::N(n.to_string()),
+        Value::String(s) => AttributeValue::S(s.to_string()),
+        Value::Array(values) => AttributeValue::L(values.iter().map(attribute_value).collect()),
+        Value::Object(fields) => AttributeValue::M(fields.iter()
+            .map(|(name, value)| (name.to_string(), attribute_value(value)))
+            .collect::<HashMap<String, AttributeValue>>()),
+    }
+}
+
+/// The audit sinks configured in the lambda environment.
+pub struct AuditSinks {
+    sinks: Vec<Box<dyn AuditSink>>,
+}
+
+impl AuditSinks {
+    pub fn from_environment() -> Self {
+        let mut sinks: Vec<Box<dyn AuditSink>> = vec![];
+
+        if std::env::var(TAGS_ENVIRONMENT_VARIABLE).map(|v| v == "true").unwrap_or(false) {
+            sinks.push(Box::new(TagAuditSink {}));
+        }
+        if let Ok(table_name) = std::env::var(TABLE_ENVIRONMENT_VARIABLE) {
+            sinks.push(Box::new(DynamoDbAuditSink { table_name }));
+        }
+        if let Ok(path) = std::env::var(FILE_ENVIRONMENT_VARIABLE) {
+            sinks.push(Box::new(FileAuditSink { path }));
+        }
+
+        AuditSinks { sinks }
+    }
+
+    /// Keeps the changes createSecret made for the token until finishSecret writes them in its record.
+    ///
+    /// Nothing is kept when there are no sinks. A failure is logged like a failed record, since the resource has already changed.
+    pub async fn record_pending_changes(&self, secret_id: &str, token: &str, changes: &[ResourceChange]) {
+        if self.sinks.is_empty() {
+            return;
+        }
+
+        let mut words = vec![token.to_string()];
+        words.extend(change_words(changes));
+
+        let result = time_api_call("TagResource", SM_CLIENT.tag_resource()
+            .secret_id(secret_id)
+            .tags(Tag::builder()
+                .key(PENDING_CHANGES_TAG)
+                .value(words.join(" "))
+                .build())
+            .send())
+            .await;
+
+        if let Err(err) = result {
+            error!("error recording pending changes secret_id={} version_id={}: {:?}", secret_id, token, err);
+        }
+    }
+
+    /// Writes the record to every sink.
+    ///
+    /// The secret's stages have already moved, so a retried step would find nothing to do.
+    /// Failures are logged rather than returned for that reason.
+    pub async fn write(&self, record: &AuditRecord) {
+        info!("rotation audit record={:?}", record);
+
+        for sink in self.sinks.iter() {
+            if let Err(err) = sink.write(record).await {
+                error!("error writing audit record secret_id={} version_id={}: {}", record.secret_id, record.version_id, err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::value::Secret;
+
+    fn changes() -> Vec<ResourceChange> {
+        vec![
+            ResourceChange::Reset { credential_id: "B".to_string() },
+            ResourceChange::Activated { credential_id: "B".to_string() },
+            ResourceChange::Deactivated { credential_id: "A".to_string() },
+        ]
+    }
+
+    fn finished() -> AuditRecord {
+        let pending = SecretValue {
+            secret: Some(Secret::default()),
+            created_date: Some(1784116800.5),
+        };
+
+        let mut record = AuditRecord::finished("secret", "v2", Some("v1"), &pending, changes(), Utc.with_ymd_and_hms(2026, 7, 15, 12, 5, 0).unwrap());
+        record.lambda_version = Some("7".to_string());
+        record
+    }
+
+    fn tags(record: &AuditRecord) -> Vec<(String, String)> {
+        record.tags().into_iter()
+            .map(|tag| (tag.key().unwrap().to_string(), tag.value().unwrap().to_string()))
+            .collect()
+    }
+
+    #[test]
+    pub fn test_audit_record() {
+        assert_eq!(serde_json::to_value(finished()).unwrap(), json!({
+            "secretId": "secret",
+            "versionId": "v2",
+            "previousVersionId": "v1",
+            "changes": [
+                {"action": "reset", "credentialId": "B"},
+                {"action": "activated", "credentialId": "B"},
+                {"action": "deactivated", "credentialId": "A"},
+            ],
+            "lambdaVersion": "7",
+            "startedAt": "2026-07-15T12:00:00.500Z",
+            "finishedAt": "2026-07-15T12:05:00.000Z",
+        }));
+    }
+
+    #[test]
+    pub fn test_audit_tags() {
+        assert_eq!(tags(&finished()), vec![
+            ("rotator:lastRotationVersion".to_string(), "v2".to_string()),
+            ("rotator:lastRotationPreviousVersion".to_string(), "v1".to_string()),
+            ("rotator:lastRotationChanges".to_string(), "reset:B activated:B deactivated:A".to_string()),
+            ("rotator:lastRotationLambdaVersion".to_string(), "7".to_string()),
+            ("rotator:lastRotationStarted".to_string(), "2026-07-15T12:00:00.500Z".to_string()),
+            ("rotator:lastRotationFinished".to_string(), "2026-07-15T12:05:00.000Z".to_string()),
+        ]);
+    }
+
+    #[test]
+    pub fn test_pending_changes() {
+        let value = format!("v2 {}", change_words(&changes()).join(" "));
+
+        assert_eq!(pending_changes(&value, "v2"), changes());
+        assert_eq!(pending_changes(&value, "v3"), vec![]);
+        assert_eq!(pending_changes("v2 unknown:A", "v2"), vec![]);
+    }
+
+    #[tokio::test]
+    pub async fn test_file_sink_appends_json_lines() {
+        let path = std::env::temp_dir().join(format!("rotator-audit-{}.jsonl", std::process::id()));
+        let sink = FileAuditSink { path: path.to_string_lossy().to_string() };
+
+        sink.write(&finished()).await.expect("audit write error");
+        sink.write(&finished()).await.expect("audit write error");
+
+        let contents = std::fs::read_to_string(&path).expect("audit read error");
+        std::fs::remove_file(&path).ok();
+
+        let lines: Vec<Value> = contents.lines().map(|line| serde_json::from_str(line).expect("json parse error")).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], lines[1]);
+        assert_eq!(lines[1]["versionId"], json!("v2"));
+    }
+}
@@ -0,0 +1,89 @@
+use serde_json::Value;
+use crate::{RotatorEvent, RotatorStep};
+
+const USAGE: &str = "usage:
+    rotator                                      run as a lambda
+    rotator rotate <secret-id> <token> <step>    run one rotation step, e.g. createSecret
+    rotator plan <secret-id> [<token>]           describe what every rotation step would do
+    rotator sweep                                check every secret managed by the rotator
+    rotator rollback <secret-id>                 restore the AWSPREVIOUS version of a secret";
+
+/// Placeholder client request token for planning before a rotation has been started.
+pub const PLAN_TOKEN: &str = "dry-run";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Lambda,
+    Rotate(RotatorEvent),
+    Plan {
+        secret_id: String,
+        client_request_token: String,
+    },
+    Sweep,
+    Rollback {
+        secret_id: String,
+    },
+}
+
+pub fn parse_args<I: Iterator<Item=String>>(args: I) -> Result<Command, String> {
+    let args: Vec<String> = args.collect();
+    let args: Vec<&str> = args.iter().map(|arg| &arg[..]).collect();
+
+    match &args[..] {
+        [] => Ok(Command::Lambda),
+        ["rotate", secret_id, token, step] => Ok(Command::Rotate(RotatorEvent {
+            secret_id: secret_id.to_string(),
+            client_request_token: token.to_string(),
+            step: parse_step(step)?,
+            dry_run: false,
+        })),
+        ["plan", secret_id] => Ok(Command::Plan {
+            secret_id: secret_id.to_string(),
+            client_request_token: PLAN_TOKEN.to_string(),
+        }),
+        ["plan", secret_id, token] => Ok(Command::Plan {
+            secret_id: secret_id.to_string(),
+            client_request_token: token.to_string(),
+        }),
+        ["sweep"] => Ok(Command::Sweep),
+        ["rollback", secret_id] => Ok(Command::Rollback {
+            secret_id: secret_id.to_string(),
+        }),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn parse_step(step: &str) -> Result<RotatorStep, String> {
+    serde_json::from_value(Value::String(step.to_string()))
+        .map_err(|_| format!("invalid step '{}'\n{}", step, USAGE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item=String> {
+        args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>().into_iter()
+    }
+
+    #[test]
+    pub fn test_parse_args() {
+        assert_eq!(parse_args(args(&[])), Ok(Command::Lambda));
+        assert_eq!(parse_args(args(&["rotate", "secret", "token", "finishSecret"])), Ok(Command::Rotate(RotatorEvent {
+            secret_id: "secret".to_string(),
+            client_request_token: "token".to_string(),
+            step: RotatorStep::FinishSecret,
+            dry_run: false,
+        })));
+        assert_eq!(parse_args(args(&["plan", "secret"])), Ok(Command::Plan {
+            secret_id: "secret".to_string(),
+            client_request_token: PLAN_TOKEN.to_string(),
+        }));
+        assert_eq!(parse_args(args(&["sweep"])), Ok(Command::Sweep));
+        assert_eq!(parse_args(args(&["rollback", "secret"])), Ok(Command::Rollback {
+            secret_id: "secret".to_string(),
+        }));
+        assert!(parse_args(args(&["rotate", "secret", "token", "nope"])).is_err());
+        assert!(parse_args(args(&["nope"])).is_err());
+    }
+}
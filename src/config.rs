@@ -1,16 +1,31 @@
-use std::iter::Iterator;
 use std::error::Error as StdError;
 use std::convert::TryFrom;
 use std::result::Result;
 use std::fmt;
-use rusoto_secretsmanager::Tag;
+use std::collections::BTreeMap;
+use aws_sdk_secretsmanager::types::Tag;
+use serde_json::Value;
 use crate::error::{RotatorResult, RotatorError};
+use crate::value::{Secret, ValueFormat};
+use crate::window::RotationWindow;
+use crate::stage::StagingLabels;
+use crate::replica::ReplicaMode;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ResourceType {
     ServiceSpecificCredential
 }
 
+impl ResourceType {
+    pub const ALL: &'static [ResourceType] = &[ResourceType::ServiceSpecificCredential];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResourceType::ServiceSpecificCredential => "ServiceSpecificCredential",
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ResourceTypeParseError(String);
 
@@ -22,7 +37,7 @@ impl StdError for ResourceTypeParseError {
 
 impl fmt::Display for ResourceTypeParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "resource type parse error: {}", self)
+        write!(f, "resource type parse error: {}", self.0)
     }
 }
 
@@ -30,61 +45,504 @@ impl TryFrom<&str> for ResourceType {
     type Error = ResourceTypeParseError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value.as_ref() {
+        match value {
             "ServiceSpecificCredential" => Ok(ResourceType::ServiceSpecificCredential),
             s => Err(ResourceTypeParseError(format!("invalid resource type '{}'", s)))
         }
     }
 }
 
+/// What to do with service specific credentials that already exist for the user
+/// but that the rotator has no record of managing.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum AdoptionMode {
+    Adopt,
+    #[default]
+    Refuse,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdoptionModeParseError(String);
+
+impl StdError for AdoptionModeParseError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for AdoptionModeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "adoption mode parse error: {}", self.0)
+    }
+}
+
+impl TryFrom<&str> for AdoptionMode {
+    type Error = AdoptionModeParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "Adopt" => Ok(AdoptionMode::Adopt),
+            "Refuse" => Ok(AdoptionMode::Refuse),
+            s => Err(AdoptionModeParseError(format!("invalid adoption mode '{}'", s)))
+        }
+    }
+}
+
+/// Where an effective configuration value came from.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ConfigSource {
+    Environment,
+    SecretValue,
+    Tag,
+}
+
+const TAG_PREFIX: &str = "rotator:";
+
+/// Every secret the rotator manages has this tag.
+pub const RESOURCE_TYPE_TAG: &str = "rotator:resourceType";
+pub const SECRET_VALUE_SECTION: &str = "rotator";
+
+/// Lambda environment variables that provide defaults for the settings of every secret.
+const ENVIRONMENT_VARIABLES: &[(&str, &str)] = &[
+    ("resourceType", "ROTATOR_RESOURCE_TYPE"),
+    ("userName", "ROTATOR_USER_NAME"),
+    ("serviceName", "ROTATOR_SERVICE_NAME"),
+    ("adoptionMode", "ROTATOR_ADOPTION_MODE"),
+    ("allowedWindow", "ROTATOR_ALLOWED_WINDOW"),
+    ("blackout", "ROTATOR_BLACKOUT"),
+    ("finishLabels", "ROTATOR_FINISH_LABELS"),
+    ("valueFormat", "ROTATOR_VALUE_FORMAT"),
+    ("replicaMode", "ROTATOR_REPLICA_MODE"),
+];
+
+/// Configuration values merged from each source, keyed by setting name without the `rotator:` prefix.
+///
+/// Sources added later override earlier ones, so add them in order of increasing precedence.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigValues {
+    values: BTreeMap<String, (String, ConfigSource)>,
+}
+
+impl ConfigValues {
+    pub fn new() -> Self {
+        ConfigValues::default()
+    }
+
+    pub fn add_environment<I: Iterator<Item=(String, String)>>(&mut self, vars: I) {
+        for (var, value) in vars {
+            if let Some((name, _)) = ENVIRONMENT_VARIABLES.iter().find(|(_, env_name)| *env_name == var) {
+                self.insert(name, &value, ConfigSource::Environment);
+            }
+        }
+    }
+
+    /// Reads the `rotator` object of the secret value, e.g. `{"rotator": {"userName": "Bob"}}`.
+    pub fn add_secret_value(&mut self, secret: &Secret) {
+        if let Some(Value::Object(section)) = secret.attributes.get(SECRET_VALUE_SECTION).map(|value| value.expose()) {
+            for (name, value) in section.iter() {
+                if name == VALUE_FORMAT_SETTING {
+                    warn!("ignoring {}{} in the secret value, it's needed before the value can be read", TAG_PREFIX, name);
+                    continue;
+                }
+                if let Value::String(value) = value {
+                    self.insert(name, value, ConfigSource::SecretValue);
+                }
+            }
+        }
+    }
+
+    pub fn add_tags(&mut self, tags: &[Tag]) {
+        for t in tags {
+            if let (Some(key), Some(value)) = (t.key.as_ref(), t.value.as_ref()) {
+                if let Some(name) = key.strip_prefix(TAG_PREFIX) {
+                    self.insert(name, value, ConfigSource::Tag);
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, name: &str, value: &str, source: ConfigSource) {
+        self.values.insert(name.to_string(), (value.to_string(), source));
+    }
+
+    pub fn get(&self, name: &str) -> Option<(&str, ConfigSource)> {
+        self.values.get(name).map(|(value, source)| (&value[..], *source))
+    }
+
+    /// Collects the values whose names start with `prefix`, keyed by the remainder of the name.
+    pub fn get_with_prefix(&self, prefix: &str) -> BTreeMap<String, String> {
+        self.values.iter()
+            .filter_map(|(name, (value, _))| match name.strip_prefix(prefix) {
+                Some(suffix) if !suffix.is_empty() => Some((suffix.to_string(), value.to_string())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item=(&str, ConfigSource)> {
+        self.values.iter().map(|(name, (_, source))| (&name[..], *source))
+    }
+
+    pub fn log_effective(&self, secret_id: &str) {
+        for (name, (value, source)) in self.values.iter() {
+            info!("effective config secret_id={} {}={:?} source={:?}", secret_id, name, value, source);
+        }
+    }
+}
+
+/// Settings recognised for every resource type.
+const COMMON_SETTINGS: &[&str] = &["resourceType", "allowedWindow", "blackout", "finishLabels", "replicaMode", VALUE_FORMAT_SETTING];
+/// Read from tags and the environment only, since the secret value can't be read without it.
+const VALUE_FORMAT_SETTING: &str = "valueFormat";
+const TEMPLATE_PREFIX: &str = "template:";
+/// Written by the audit tag sink, lease store, createSecret and password-only values rather than read as config.
+const AUDIT_PREFIX: &str = "lastRotation";
+const RECORD_SETTINGS: &[&str] = &["lease", "pendingCredential", "pendingChanges", "credentials"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceSpecificCredentialConfig {
+    pub user_name: String,
+    pub service_name: String,
+    pub adoption_mode: AdoptionMode,
+}
+
+impl ServiceSpecificCredentialConfig {
+    const SETTINGS: &'static [&'static str] = &["userName", "serviceName", "adoptionMode"];
+
+    fn parse(parser: &mut ConfigParser) -> Option<Self> {
+        let user_name = parser.required("userName");
+        let service_name = parser.required("serviceName");
+        let adoption_mode = parser.optional("adoptionMode").unwrap_or_default();
+
+        Some(ServiceSpecificCredentialConfig {
+            user_name: user_name?,
+            service_name: service_name?,
+            adoption_mode,
+        })
+    }
+}
+
+/// The settings specific to each resource type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResourceConfig {
+    ServiceSpecificCredential(ServiceSpecificCredentialConfig),
+}
+
+impl ResourceConfig {
+    fn settings(resource_type: ResourceType) -> &'static [&'static str] {
+        match resource_type {
+            ResourceType::ServiceSpecificCredential => ServiceSpecificCredentialConfig::SETTINGS,
+        }
+    }
+
+    fn parse(resource_type: ResourceType, parser: &mut ConfigParser) -> Option<Self> {
+        match resource_type {
+            ResourceType::ServiceSpecificCredential => ServiceSpecificCredentialConfig::parse(parser)
+                .map(ResourceConfig::ServiceSpecificCredential),
+        }
+    }
+}
+
+/// Parses settings from config values, collecting every missing or invalid setting
+/// so they can all be reported at once.
+struct ConfigParser<'a> {
+    values: &'a ConfigValues,
+    missing: Vec<String>,
+    invalid: Vec<String>,
+}
+
+impl<'a> ConfigParser<'a> {
+    fn new(values: &'a ConfigValues) -> Self {
+        ConfigParser {
+            values,
+            missing: vec![],
+            invalid: vec![],
+        }
+    }
+
+    fn optional<E: StdError, T: TryFrom<&'a str, Error=E>>(&mut self, name: &str) -> Option<T> {
+        let (value, source) = self.values.get(name)?;
+
+        match T::try_from(value) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.invalid.push(format!("{}{} from {:?}: {}", TAG_PREFIX, name, source, err));
+                None
+            },
+        }
+    }
+
+    fn required<E: StdError, T: TryFrom<&'a str, Error=E>>(&mut self, name: &str) -> Option<T> {
+        if self.values.get(name).is_none() {
+            self.missing.push(format!("{}{}", TAG_PREFIX, name));
+        }
+
+        self.optional(name)
+    }
+
+    fn warn_unknown(&self, secret_id: &str, known: &[&str]) {
+        for (name, source) in self.values.names() {
+            if !known.contains(&name) && !name.starts_with(TEMPLATE_PREFIX) && !name.starts_with(AUDIT_PREFIX) && !RECORD_SETTINGS.contains(&name) {
+                warn!("unknown config setting {}{} from {:?} for secret {}", TAG_PREFIX, name, source, secret_id);
+            }
+        }
+    }
+
+    fn finish<T>(self, secret_id: &str, value: Option<T>) -> RotatorResult<T> {
+        match value {
+            Some(value) if self.missing.is_empty() && self.invalid.is_empty() => Ok(value),
+            _ => Err(RotatorError::InvalidTags {
+                secret_id: secret_id.to_string(),
+                missing: self.missing,
+                invalid: self.invalid,
+            }),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Config {
     pub secret_id: String,
-    pub resource_type: ResourceType,
-    pub user_name: Option<String>,
-    pub service_name: Option<String>,
+    pub resource: ResourceConfig,
+    pub templates: BTreeMap<String, String>,
+    pub window: RotationWindow,
+    /// Custom staging labels moved to the new version by finishSecret.
+    pub finish_labels: Vec<String>,
+    /// How the secret's value is stored.
+    pub value_format: ValueFormat,
+    /// Whether testSecret waits for replicas to have the new version.
+    pub replica_mode: ReplicaMode,
+}
+
+/// The value format of a secret, from the lambda environment and the secret's tags.
+///
+/// This is needed to read the current value, which the rest of the config comes from.
+pub fn value_format(secret_id: &str, tags: Option<&[Tag]>) -> RotatorResult<ValueFormat> {
+    let mut values = ConfigValues::new();
+
+    values.add_environment(std::env::vars());
+    if let Some(tags) = tags {
+        values.add_tags(tags);
+    }
+
+    let mut parser = ConfigParser::new(&values);
+    let format = parser.optional(VALUE_FORMAT_SETTING).unwrap_or_default();
+
+    parser.finish(secret_id, Some(format))
 }
 
 impl Config {
-    pub fn new_for_secret(secret_id: &str, tags: Option<Vec<Tag>>) -> RotatorResult<Self> {
-        let tags = tags.ok_or_else(|| RotatorError::MissingTags {
-            secret_id: secret_id.to_string(),
-        })?;
+    /// Builds the config for a secret from, in increasing order of precedence,
+    /// the lambda environment, the `rotator` section of the current secret value and the secret's tags.
+    pub fn new_for_secret(secret_id: &str, tags: Option<Vec<Tag>>, secret: Option<&Secret>) -> RotatorResult<Self> {
+        let mut values = ConfigValues::new();
+
+        values.add_environment(std::env::vars());
+        if let Some(secret) = secret {
+            values.add_secret_value(secret);
+        }
+        if let Some(tags) = tags {
+            values.add_tags(&tags);
+        }
+
+        values.log_effective(secret_id);
+
+        Self::from_values(secret_id, &values)
+    }
+
+    pub fn from_values(secret_id: &str, values: &ConfigValues) -> RotatorResult<Self> {
+        let mut parser = ConfigParser::new(values);
+
+        let window = RotationWindow {
+            allowed: parser.optional("allowedWindow"),
+            blackout: parser.optional("blackout"),
+        };
+
+        let finish_labels = parser.optional("finishLabels")
+            .map(|StagingLabels(labels)| labels)
+            .unwrap_or_default();
+
+        let value_format: ValueFormat = parser.optional(VALUE_FORMAT_SETTING).unwrap_or_default();
+        let replica_mode = parser.optional("replicaMode").unwrap_or_default();
+
+        let resource_type: Option<ResourceType> = parser.required("resourceType");
+
+        // without a resource type, any type's settings may be intended
+        let resource_types = match resource_type {
+            Some(ref resource_type) => std::slice::from_ref(resource_type),
+            None => ResourceType::ALL,
+        };
+        let known: Vec<&str> = COMMON_SETTINGS.iter()
+            .chain(resource_types.iter().flat_map(|resource_type| ResourceConfig::settings(*resource_type).iter()))
+            .cloned()
+            .collect();
+        parser.warn_unknown(secret_id, &known);
+
+        let resource = match resource_type {
+            Some(resource_type) => {
+                ResourceConfig::parse(resource_type, &mut parser)
+            },
+            None => {
+                // settings can't be missing for an unknown type, but values that no type accepts are still invalid
+                for resource_type in ResourceType::ALL.iter() {
+                    let mut type_parser = ConfigParser::new(values);
+                    ResourceConfig::parse(*resource_type, &mut type_parser);
+
+                    for invalid in type_parser.invalid {
+                        if !parser.invalid.contains(&invalid) {
+                            parser.invalid.push(invalid);
+                        }
+                    }
+                }
+                None
+            },
+        };
 
         Ok(Config {
             secret_id: secret_id.to_string(),
-            resource_type: Self::get_required_tag(secret_id, tags.iter(), "rotator:resourceType")?,
-            service_name: Self::get_tag(secret_id, tags.iter(), "rotator:serviceName")?,
-            user_name: Self::get_tag(secret_id, tags.iter(), "rotator:userName")?,
+            resource: parser.finish(secret_id, resource)?,
+            templates: values.get_with_prefix(TEMPLATE_PREFIX),
+            window,
+            finish_labels,
+            value_format,
+            replica_mode,
         })
     }
 
-    pub fn get_required_tag<'a, E: StdError, T: TryFrom<&'a str, Error=E>, I: Iterator<Item=&'a Tag>>(secret_id: &str, tags: I, name: &str) -> RotatorResult<T> {
-        Self::get_tag(secret_id, tags, name)
-            .and_then(|value| value.ok_or_else(|| RotatorError::MissingTag {
-                secret_id: secret_id.to_string(),
-                tag_name: name.to_string(),
-            }))
+    pub fn resource_type(&self) -> ResourceType {
+        match self.resource {
+            ResourceConfig::ServiceSpecificCredential(_) => ResourceType::ServiceSpecificCredential,
+        }
     }
+}
 
-    pub fn get_tag<'a, E: StdError, T: TryFrom<&'a str, Error=E>, I: Iterator<Item=&'a Tag>>(secret_id: &str, tags: I, name: &str) -> RotatorResult<Option<T>> {
-        for t in tags {
-            if let Some(ref key) = t.key {
-                if key == name {
-                    if let Some(ref value) = t.value {
-                        return T::try_from(value)
-                            .map(|v| Some(v))
-                            .map_err(|err: E| RotatorError::SerialisationError {
-                                secret_id: secret_id.to_string(),
-                                message: format!("{:?}", err),
-                            });
-                    } else {
-                        return Ok(None);
-                    }
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(key: &str, value: &str) -> Tag {
+        Tag::builder()
+            .key(key)
+            .value(value)
+            .build()
+    }
+
+    #[test]
+    pub fn test_config_precedence() {
+        let secret: Secret = serde_json::from_str(r#"
+        {
+            "rotator": {
+                "userName": "Alice",
+                "serviceName": "cassandra.amazonaws.com"
             }
         }
+        "#).expect("json parse error");
+
+        let mut values = ConfigValues::new();
+        values.add_environment(vec![
+            ("ROTATOR_RESOURCE_TYPE".to_string(), "ServiceSpecificCredential".to_string()),
+            ("ROTATOR_SERVICE_NAME".to_string(), "codecommit.amazonaws.com".to_string()),
+            ("HOME".to_string(), "/".to_string()),
+        ].into_iter());
+        values.add_secret_value(&secret);
+        values.add_tags(&[tag("rotator:userName", "Bob"), tag("rotator:finishLabels", "ROTATOR_LAST_GOOD"), tag("Name", "ignored")]);
+
+        assert_eq!(values.get("resourceType"), Some(("ServiceSpecificCredential", ConfigSource::Environment)));
+        assert_eq!(values.get("serviceName"), Some(("cassandra.amazonaws.com", ConfigSource::SecretValue)));
+        assert_eq!(values.get("userName"), Some(("Bob", ConfigSource::Tag)));
+        assert_eq!(values.get("Name"), None);
+
+        let config = Config::from_values("secret", &values).expect("config error");
 
-        Ok(None)
+        assert_eq!(config.resource, ResourceConfig::ServiceSpecificCredential(ServiceSpecificCredentialConfig {
+            user_name: "Bob".to_string(),
+            service_name: "cassandra.amazonaws.com".to_string(),
+            adoption_mode: AdoptionMode::Refuse,
+        }));
+        assert_eq!(config.finish_labels, vec!["ROTATOR_LAST_GOOD".to_string()]);
+        assert_eq!(config.replica_mode, ReplicaMode::Ignore);
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_config_missing_resource_type() {
+        let result = Config::from_values("secret", &ConfigValues::new());
+
+        assert_eq!(result, Err(RotatorError::InvalidTags {
+            secret_id: "secret".to_string(),
+            missing: vec!["rotator:resourceType".to_string()],
+            invalid: vec![],
+        }));
+    }
+
+    #[test]
+    pub fn test_config_invalid_resource_type_reports_other_problems() {
+        let mut values = ConfigValues::new();
+        values.add_tags(&[
+            tag("rotator:resourceType", "AccessKey"),
+            tag("rotator:blackout", "Someday"),
+            tag("rotator:adoptionMode", "Maybe"),
+            tag("rotator:userName", "Bob"),
+        ]);
+
+        let result = Config::from_values("secret", &values);
+
+        let invalid = match result {
+            Err(RotatorError::InvalidTags { missing, invalid, .. }) => {
+                assert!(missing.is_empty());
+                invalid
+            },
+            other => panic!("expected InvalidTags, got {:?}", other),
+        };
+        assert_eq!(invalid.len(), 3);
+        assert!(invalid[0].starts_with("rotator:blackout from Tag"));
+        assert!(invalid[1].starts_with("rotator:resourceType from Tag"));
+        assert_eq!(invalid[2], "rotator:adoptionMode from Tag: adoption mode parse error: invalid adoption mode 'Maybe'");
+    }
+
+    #[test]
+    pub fn test_config_reports_all_problems() {
+        let mut values = ConfigValues::new();
+        values.add_tags(&[
+            tag("rotator:resourceType", "ServiceSpecificCredential"),
+            tag("rotator:adoptionMode", "Maybe"),
+            tag("rotator:userNmae", "Bob"),
+        ]);
+
+        let result = Config::from_values("secret", &values);
+
+        assert_eq!(result, Err(RotatorError::InvalidTags {
+            secret_id: "secret".to_string(),
+            missing: vec!["rotator:userName".to_string(), "rotator:serviceName".to_string()],
+            invalid: vec!["rotator:adoptionMode from Tag: adoption mode parse error: invalid adoption mode 'Maybe'".to_string()],
+        }));
+    }
+
+    #[test]
+    pub fn test_config_value_format() {
+        let secret: Secret = serde_json::from_str(r#"{"rotator": {"valueFormat": "KeyValue"}}"#).expect("json parse error");
+
+        let mut values = ConfigValues::new();
+        values.add_secret_value(&secret);
+        values.add_tags(&[
+            tag("rotator:resourceType", "ServiceSpecificCredential"),
+            tag("rotator:userName", "Bob"),
+            tag("rotator:serviceName", "codecommit.amazonaws.com"),
+        ]);
+
+        assert_eq!(values.get("valueFormat"), None);
+        assert_eq!(Config::from_values("secret", &values).map(|config| config.value_format), Ok(ValueFormat::Json));
+
+        values.add_tags(&[tag("rotator:valueFormat", "KeyValue")]);
+        assert_eq!(Config::from_values("secret", &values).map(|config| config.value_format), Ok(ValueFormat::KeyValue));
+
+        values.add_tags(&[tag("rotator:valueFormat", "Binary")]);
+        assert_eq!(Config::from_values("secret", &values).map(|config| config.value_format), Ok(ValueFormat::Binary));
+
+        values.add_tags(&[tag("rotator:valueFormat", "Plain")]);
+        assert_eq!(Config::from_values("secret", &values), Err(RotatorError::InvalidTags {
+            secret_id: "secret".to_string(),
+            missing: vec![],
+            invalid: vec!["rotator:valueFormat from Tag: value format parse error: invalid value format 'Plain'".to_string()],
+        }));
+    }
+}
@@ -1,35 +1,121 @@
-use std::time::Duration;
 use crate::{
     Resource,
     RotatorError,
     RotatorEvent,
     RotatorResult,
+    StepOutcome,
 };
-use crate::value::{get_secret_value, put_secret_value};
+use crate::audit::AuditSinks;
+use crate::config::Config;
+use crate::template::{check_templates, render_into_secret};
+use crate::value::{encode_secret, get_secret_value, put_secret_value, SecretValue};
+use crate::stage::{AWSCURRENT, AWSPENDING, AWSPREVIOUS};
+use crate::schema::{CURRENT_SCHEMA_VERSION, Payload, legacy_managed_credential_ids, migrate, pending_migrations};
 
-pub fn create_secret(e: RotatorEvent, r: Box<dyn Resource>) -> RotatorResult<()> {
-    let timeout = Duration::from_secs(2);
+/// Checks a value built from the current one would have the resource type's fields and fit the value format,
+/// using placeholder credentials, so that a bad config fails before the credential is changed.
+fn check_new_value(e: &RotatorEvent, config: &Config, current: &SecretValue) -> RotatorResult<()> {
+    let secret = check_templates(&config.templates, current.secret.as_ref())
+        .map_err(|err| RotatorError::InvalidConfig {
+            secret_id: e.secret_id.to_string(),
+            message: format!("{}", err),
+        })?;
 
-    let current = get_secret_value(&e.secret_id, Some("AWSCURRENT"), None, timeout)?;
+    Payload::from_secret(&e.secret_id, config.resource_type(), &secret)?;
+    encode_secret(&e.secret_id, config.value_format, &secret)?;
 
-    match get_secret_value(&e.secret_id, Some("AWSPENDING"), Some(&e.client_request_token), timeout) {
+    Ok(())
+}
+
+/// Records the credentials of a value written before they were recorded, from its previous version.
+async fn record_legacy_credential_ids(e: &RotatorEvent, config: &Config, current: &mut SecretValue) -> RotatorResult<()> {
+    let secret = match current.secret {
+        Some(ref mut secret) if secret.managed_credential_ids.is_empty() && secret.service_specific_credential_id.is_some() => secret,
+        _ => return Ok(()),
+    };
+
+    let previous = match get_secret_value(&e.secret_id, config.value_format, Some(AWSPREVIOUS), None).await {
+        Ok(previous) => previous.secret,
+        Err(RotatorError::SecretValueNotFound { .. }) | Err(RotatorError::SerialisationError { .. }) => None,
+        Err(err) => return Err(err),
+    };
+
+    secret.managed_credential_ids = legacy_managed_credential_ids(secret, previous.as_ref());
+    info!("recorded managed credentials {:?} for secret {} from its previous version", secret.managed_credential_ids, e.secret_id);
+
+    Ok(())
+}
+
+pub async fn create_secret(e: RotatorEvent, config: &Config, r: Box<dyn Resource>) -> RotatorResult<StepOutcome> {
+    let mut current = get_secret_value(&e.secret_id, config.value_format, Some(AWSCURRENT), None).await?;
+
+    match get_secret_value(&e.secret_id, config.value_format, Some(AWSPENDING), Some(&e.client_request_token)).await {
         Ok(_) => {
             info!("createSecret: Successfully retrieved secret for {}.", e.secret_id);
-            Ok(())
+            Ok(StepOutcome::no_op(vec![e.client_request_token.to_string()]))
         },
         Err(RotatorError::SecretValueNotFound { .. }) => {
-            let secret = r.create_new_password(current)?;
+            if let Some(ref mut secret) = current.secret {
+                migrate(&e.secret_id, secret)?;
+            }
+            record_legacy_credential_ids(&e, config, &mut current).await?;
+
+            check_new_value(&e, config, &current)?;
+
+            let (mut secret, changes) = r.create_new_password(&e.client_request_token, current).await?;
+            // the resource has changed even if putting the pending value fails below
+            AuditSinks::from_environment().record_pending_changes(&e.secret_id, &e.client_request_token, &changes).await;
+
+            secret.schema_version = Some(CURRENT_SCHEMA_VERSION);
 
-            put_secret_value(&e.secret_id, &e.client_request_token, &secret, "AWSPENDING", timeout)?;
+            render_into_secret(&config.templates, &mut secret)
+                .map_err(|err| RotatorError::InvalidConfig {
+                    secret_id: e.secret_id.to_string(),
+                    message: format!("{}", err),
+                })?;
+
+            Payload::from_secret(&e.secret_id, config.resource_type(), &secret)?;
+
+            put_secret_value(&e.secret_id, config.value_format, &e.client_request_token, &secret, AWSPENDING).await?;
             info!("createSecret: Successfully put secret for ARN {} and version {}.", e.secret_id, e.client_request_token);
 
-            Ok(())
+            Ok(StepOutcome {
+                no_op: false,
+                changes,
+                versions: vec![e.client_request_token.to_string()],
+            })
         },
         Err(err) => {
             error!("createSecret: Error retrieving secret for ARN {} and version {}: {:?}.", e.secret_id, e.client_request_token, err);
             Err(err)
         }
-    }?;
-    
-    Ok(())
-}
\ No newline at end of file
+    }
+}
+
+pub async fn plan_create_secret(e: &RotatorEvent, config: &Config, r: &dyn Resource) -> RotatorResult<Vec<String>> {
+    let mut current = get_secret_value(&e.secret_id, config.value_format, Some(AWSCURRENT), None).await?;
+
+    match get_secret_value(&e.secret_id, config.value_format, Some(AWSPENDING), Some(&e.client_request_token)).await {
+        Ok(_) => Ok(vec![format!("version {} already has a pending secret, nothing to do", e.client_request_token)]),
+        Err(RotatorError::SecretValueNotFound { .. }) => {
+            let mut plan = vec![];
+            if let Some(ref secret) = current.secret {
+                for (version, description) in pending_migrations(secret) {
+                    plan.push(format!("would migrate the secret value from schema version {}: {}", version, description));
+                }
+            }
+
+            record_legacy_credential_ids(e, config, &mut current).await?;
+            check_new_value(e, config, &current)?;
+
+            plan.extend(r.plan_new_password(&e.client_request_token, &current).await?);
+            for field in config.templates.keys() {
+                plan.push(format!("would render template field {}", field));
+            }
+            plan.push(format!("would put secret version {} as AWSPENDING", e.client_request_token));
+
+            Ok(plan)
+        },
+        Err(err) => Err(err),
+    }
+}
@@ -1,29 +1,22 @@
-use std::time::Duration;
-use rusoto_core::RusotoError;
-use rusoto_secretsmanager::{
-    SecretsManager,
-    DescribeSecretRequest,
-    DescribeSecretResponse,
-    DescribeSecretError,
-};
+use aws_sdk_secretsmanager::operation::describe_secret::DescribeSecretOutput;
 use crate::error::{RotatorResult, RotatorError};
 use crate::SM_CLIENT;
+use crate::metrics::time_api_call;
 
-pub fn describe_secret(secret_id: &str, timeout: Duration) -> RotatorResult<DescribeSecretResponse> {
+pub async fn describe_secret(secret_id: &str) -> RotatorResult<DescribeSecretOutput> {
     info!("desribing secret {}", secret_id);
 
-    SM_CLIENT.describe_secret(DescribeSecretRequest {
-        secret_id: secret_id.to_string()
-    })
-        .with_timeout(timeout)
-        .sync()
-        .map_err(|e| match e {
-            RusotoError::Service(DescribeSecretError::ResourceNotFound(msg)) => {
+    time_api_call("DescribeSecret", SM_CLIENT.describe_secret()
+        .secret_id(secret_id)
+        .send())
+        .await
+        .map_err(|e| match e.as_service_error() {
+            Some(err) if err.is_resource_not_found_exception() => {
                 RotatorError::SecretNotFound {
                     secret_id: secret_id.to_string(),
-                    message: msg,
+                    message: err.meta().message().unwrap_or_default().to_string(),
                 }
             },
-            e => RotatorError::DescribeSecretError(format!("{:?}", e))
-        })  
-}
\ No newline at end of file
+            _ => RotatorError::from_sdk("DescribeSecret", &e).unwrap_or_else(|| RotatorError::DescribeSecretError(format!("{:?}", e)))
+        })
+}
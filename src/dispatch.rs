@@ -0,0 +1,185 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::{RotatorEvent, RotatorResult, RotatorError};
+use crate::cli::PLAN_TOKEN;
+use crate::drift::CloudTrailEvent;
+use crate::sweep::ScheduledEvent;
+
+const SCHEDULED_EVENT: &str = "Scheduled Event";
+const CLOUDTRAIL_EVENT: &str = "AWS API Call via CloudTrail";
+
+/// Anything the lambda may be invoked with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RotatorInput {
+    /// A rotation step from Secrets Manager.
+    Rotation(RotatorEvent),
+    /// An EventBridge schedule, which starts a sweep.
+    Scheduled(ScheduledEvent),
+    /// An IAM call recorded by CloudTrail.
+    CloudTrail(CloudTrailEvent),
+    /// An operator's request, e.g. from `aws lambda invoke`.
+    Admin(AdminCommand),
+    HealthCheck,
+}
+
+/// A command sent to the lambda directly, tagged by its `Command` field.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "Command")]
+pub enum AdminCommand {
+    /// Check every secret managed by the rotator.
+    #[serde(rename="sweep")]
+    Sweep,
+
+    /// Describe what every rotation step would do.
+    #[serde(rename="plan")]
+    Plan {
+        #[serde(rename="SecretId")]
+        secret_id: String,
+
+        #[serde(rename="ClientRequestToken", default="plan_token")]
+        client_request_token: String,
+    },
+
+    /// Ask Secrets Manager to start a rotation now.
+    #[serde(rename="rotate")]
+    Rotate {
+        #[serde(rename="SecretId")]
+        secret_id: String,
+    },
+
+    /// Restore the AWSPREVIOUS version.
+    #[serde(rename="rollback")]
+    Rollback {
+        #[serde(rename="SecretId")]
+        secret_id: String,
+    },
+}
+
+fn plan_token() -> String {
+    PLAN_TOKEN.to_string()
+}
+
+/// The response to an admin command that has no report of its own.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminOutput {
+    pub message: String,
+    pub secret_id: String,
+}
+
+impl RotatorInput {
+    /// Works out what kind of event this is from the fields it has, then parses it as that kind.
+    ///
+    /// Secrets Manager events have `Step`, EventBridge events have `detail-type`,
+    /// admin commands have `Command` and health checks have `HealthCheck`.
+    pub fn parse(event: Value) -> RotatorResult<Self> {
+        let fields = match event {
+            Value::Object(ref fields) => fields,
+            _ => return Err(invalid(format!("expected a JSON object, got {}", kind_of(&event)))),
+        };
+
+        if fields.contains_key("Command") {
+            serde_json::from_value(event)
+                .map(RotatorInput::Admin)
+                .map_err(|err| invalid(format!("invalid admin command: {}", err)))
+        } else if fields.contains_key("HealthCheck") {
+            Ok(RotatorInput::HealthCheck)
+        } else if fields.contains_key("Step") || fields.contains_key("ClientRequestToken") {
+            serde_json::from_value(event)
+                .map(RotatorInput::Rotation)
+                .map_err(|err| invalid(format!("invalid Secrets Manager rotation event: {}", err)))
+        } else if let Some(detail_type) = fields.get("detail-type") {
+            match detail_type.as_str() {
+                Some(SCHEDULED_EVENT) => serde_json::from_value(event)
+                    .map(RotatorInput::Scheduled)
+                    .map_err(|err| invalid(format!("invalid scheduled event: {}", err))),
+                Some(CLOUDTRAIL_EVENT) => serde_json::from_value(event)
+                    .map(RotatorInput::CloudTrail)
+                    .map_err(|err| invalid(format!("invalid CloudTrail event: {}", err))),
+                Some(other) => Err(invalid(format!("unsupported EventBridge event '{}'", other))),
+                None => Err(invalid(format!("EventBridge detail-type should be a string, got {}", kind_of(detail_type)))),
+            }
+        } else {
+            let keys: Vec<&str> = fields.keys().map(|key| &key[..]).collect();
+
+            Err(invalid(format!("unrecognised event with fields [{}]: expected a rotation event (Step), an EventBridge event (detail-type), an admin command (Command) or a health check (HealthCheck)", keys.join(", "))))
+        }
+    }
+}
+
+fn invalid(message: String) -> RotatorError {
+    RotatorError::InvalidEvent { message }
+}
+
+fn kind_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::RotatorStep;
+
+    fn message(result: RotatorResult<RotatorInput>) -> String {
+        match result {
+            Err(RotatorError::InvalidEvent { message }) => message,
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    pub fn test_parse_events() {
+        match RotatorInput::parse(json!({"SecretId": "secret", "ClientRequestToken": "token", "Step": "setSecret"})) {
+            Ok(RotatorInput::Rotation(e)) => assert_eq!(e.step, RotatorStep::SetSecret),
+            other => panic!("unexpected input {:?}", other),
+        }
+
+        match RotatorInput::parse(json!({"detail-type": "Scheduled Event", "source": "aws.events", "detail": {}})) {
+            Ok(RotatorInput::Scheduled(e)) => assert_eq!(e.source, "aws.events"),
+            other => panic!("unexpected input {:?}", other),
+        }
+
+        match RotatorInput::parse(json!({"detail-type": "AWS API Call via CloudTrail", "source": "aws.iam", "detail": {"eventName": "DeleteServiceSpecificCredential"}})) {
+            Ok(RotatorInput::CloudTrail(e)) => assert_eq!(e.detail.event_name, "DeleteServiceSpecificCredential"),
+            other => panic!("unexpected input {:?}", other),
+        }
+
+        assert_eq!(RotatorInput::parse(json!({"HealthCheck": true})), Ok(RotatorInput::HealthCheck));
+    }
+
+    #[test]
+    pub fn test_parse_admin_commands() {
+        assert_eq!(RotatorInput::parse(json!({"Command": "sweep"})), Ok(RotatorInput::Admin(AdminCommand::Sweep)));
+        assert_eq!(RotatorInput::parse(json!({"Command": "plan", "SecretId": "secret"})), Ok(RotatorInput::Admin(AdminCommand::Plan {
+            secret_id: "secret".to_string(),
+            client_request_token: PLAN_TOKEN.to_string(),
+        })));
+        assert_eq!(RotatorInput::parse(json!({"Command": "rotate", "SecretId": "secret"})), Ok(RotatorInput::Admin(AdminCommand::Rotate {
+            secret_id: "secret".to_string(),
+        })));
+
+        assert_eq!(RotatorInput::parse(json!({"Command": "rollback", "SecretId": "secret"})), Ok(RotatorInput::Admin(AdminCommand::Rollback {
+            secret_id: "secret".to_string(),
+        })));
+
+        assert!(message(RotatorInput::parse(json!({"Command": "delete"}))).starts_with("invalid admin command: unknown variant `delete`"));
+        assert!(message(RotatorInput::parse(json!({"Command": "rotate"}))).contains("missing field `SecretId`"));
+    }
+
+    #[test]
+    pub fn test_parse_malformed_events() {
+        assert_eq!(message(RotatorInput::parse(json!([]))), "expected a JSON object, got an array");
+        assert_eq!(message(RotatorInput::parse(json!({"SecretId": "secret", "Step": "createSecret"}))), "invalid Secrets Manager rotation event: missing field `ClientRequestToken`");
+        assert!(message(RotatorInput::parse(json!({"SecretId": "secret", "ClientRequestToken": "token", "Step": "wipeSecret"}))).contains("unknown variant `wipeSecret`"));
+        assert_eq!(message(RotatorInput::parse(json!({"detail-type": "EC2 Instance State-change Notification"}))), "unsupported EventBridge event 'EC2 Instance State-change Notification'");
+        assert!(message(RotatorInput::parse(json!({"foo": 1}))).starts_with("unrecognised event with fields [foo]"));
+    }
+}
@@ -0,0 +1,269 @@
+use std::convert::TryFrom;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::{RotatorResult, current_secret};
+use crate::config::{RESOURCE_TYPE_TAG, value_format};
+use crate::iam::CredentialStatus;
+use crate::list::list_secrets_with_tag;
+use crate::notify::{Notification, Notifiers};
+use crate::trigger::rotate_secret;
+
+const DRIFT_ACTION_ENVIRONMENT_VARIABLE: &str = "ROTATOR_DRIFT_ACTION";
+const FUNCTION_NAME_ENVIRONMENT_VARIABLE: &str = "AWS_LAMBDA_FUNCTION_NAME";
+
+/// A CloudTrail API call delivered by EventBridge.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct CloudTrailEvent {
+    #[serde(rename="detail-type")]
+    pub detail_type: String,
+
+    pub detail: CloudTrailDetail,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudTrailDetail {
+    pub event_name: String,
+
+    #[serde(default)]
+    pub user_identity: Option<UserIdentity>,
+
+    #[serde(default)]
+    pub request_parameters: Option<Value>,
+
+    /// Set if the call failed, in which case nothing changed.
+    #[serde(default)]
+    pub error_code: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct UserIdentity {
+    #[serde(default)]
+    pub arn: Option<String>,
+}
+
+/// What to do when a secret's current credential is changed outside the rotator.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize)]
+pub enum DriftAction {
+    #[default]
+    Alert,
+    Rotate,
+}
+
+impl TryFrom<&str> for DriftAction {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "Alert" => Ok(DriftAction::Alert),
+            "Rotate" => Ok(DriftAction::Rotate),
+            s => Err(format!("invalid drift action '{}'", s)),
+        }
+    }
+}
+
+impl DriftAction {
+    pub fn from_environment() -> Self {
+        match std::env::var(DRIFT_ACTION_ENVIRONMENT_VARIABLE) {
+            Ok(value) => DriftAction::try_from(&value[..]).unwrap_or_else(|err| {
+                error!("{}, alerting instead", err);
+                DriftAction::Alert
+            }),
+            Err(_) => DriftAction::Alert,
+        }
+    }
+
+    pub fn remediation(&self) -> &'static str {
+        match self {
+            DriftAction::Alert => "Rotate the secret to replace the credential.",
+            DriftAction::Rotate => "The secret is being rotated.",
+        }
+    }
+}
+
+/// A change to a service specific credential made outside the rotator.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CredentialChange {
+    pub event_name: String,
+    pub credential_id: String,
+    pub status: Option<CredentialStatus>,
+    pub changed_by: Option<String>,
+}
+
+impl CredentialChange {
+    /// Reads a credential change from the event.
+    ///
+    /// Returns `None` for other API calls, failed calls and calls made by this lambda while rotating.
+    pub fn from_event(e: &CloudTrailEvent, function_name: Option<&str>) -> Option<Self> {
+        let detail = &e.detail;
+
+        match &detail.event_name[..] {
+            "ResetServiceSpecificCredential" | "DeleteServiceSpecificCredential" | "UpdateServiceSpecificCredential" => {},
+            _ => return None,
+        }
+
+        if detail.error_code.is_some() {
+            return None;
+        }
+
+        let changed_by = detail.user_identity.as_ref().and_then(|identity| identity.arn.clone());
+
+        // lambda sessions are named after the function
+        if let (Some(arn), Some(function_name)) = (changed_by.as_ref(), function_name) {
+            if arn.ends_with(&format!("/{}", function_name)) {
+                return None;
+            }
+        }
+
+        let parameters = detail.request_parameters.as_ref()?;
+
+        Some(CredentialChange {
+            event_name: detail.event_name.to_string(),
+            credential_id: parameters.get("serviceSpecificCredentialId")?.as_str()?.to_string(),
+            status: parameters.get("status").and_then(Value::as_str).map(CredentialStatus::from),
+            changed_by,
+        })
+    }
+
+    /// Whether a secret using the credential no longer works.
+    pub fn makes_stale(&self) -> bool {
+        match &self.event_name[..] {
+            "UpdateServiceSpecificCredential" => self.status != Some(CredentialStatus::Active),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftReport {
+    pub event_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_id: Option<String>,
+    /// Secrets whose AWSCURRENT value uses the credential.
+    pub secrets: Vec<String>,
+    pub action: Option<DriftAction>,
+}
+
+/// Finds the secrets using a changed credential, then rotates them or sends an alert.
+pub async fn handle_credential_event(e: &CloudTrailEvent) -> RotatorResult<DriftReport> {
+    let function_name = std::env::var(FUNCTION_NAME_ENVIRONMENT_VARIABLE).ok();
+
+    let mut report = DriftReport {
+        event_name: e.detail.event_name.to_string(),
+        credential_id: None,
+        secrets: vec![],
+        action: None,
+    };
+
+    let change = match CredentialChange::from_event(e, function_name.as_deref()) {
+        Some(change) => change,
+        None => {
+            info!("ignoring {} event", e.detail.event_name);
+            return Ok(report);
+        },
+    };
+    report.credential_id = Some(change.credential_id.to_string());
+
+    if !change.makes_stale() {
+        info!("ignoring {} of credential {} to status {:?}", change.event_name, change.credential_id, change.status);
+        return Ok(report);
+    }
+
+    report.secrets = secrets_using_credential(&change.credential_id).await?;
+    if report.secrets.is_empty() {
+        info!("no secret is currently using credential {}", change.credential_id);
+        return Ok(report);
+    }
+
+    let action = DriftAction::from_environment();
+    report.action = Some(action);
+
+    let notifiers = Notifiers::from_environment();
+    for secret_id in report.secrets.iter() {
+        warn!("credential {} for secret {} was changed outside the rotator: {} by {:?}", change.credential_id, secret_id, change.event_name, change.changed_by);
+
+        if action == DriftAction::Rotate {
+            if let Err(err) = rotate_secret(secret_id).await {
+                error!("error starting rotation of secret {}: {:?}", secret_id, err);
+            }
+        }
+
+        notifiers.notify(&Notification::credential_changed(secret_id, &change, action)).await;
+    }
+
+    Ok(report)
+}
+
+async fn secrets_using_credential(credential_id: &str) -> RotatorResult<Vec<String>> {
+    let mut secrets = vec![];
+
+    for entry in list_secrets_with_tag(RESOURCE_TYPE_TAG).await? {
+        let secret_id = match entry.arn.or(entry.name) {
+            Some(secret_id) => secret_id,
+            None => continue,
+        };
+
+        let current = match value_format(&secret_id, entry.tags.as_deref()) {
+            Ok(format) => current_secret(&secret_id, format).await,
+            Err(err) => Err(err),
+        };
+
+        match current {
+            Ok(Some(secret)) if secret.service_specific_credential_id.as_deref() == Some(credential_id) => secrets.push(secret_id),
+            Ok(_) => {},
+            Err(err) => error!("error reading secret {} while looking for credential {}: {:?}", secret_id, credential_id, err),
+        }
+    }
+
+    Ok(secrets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(event_name: &str, parameters: Value, arn: &str) -> CloudTrailEvent {
+        serde_json::from_value(serde_json::json!({
+            "version": "0",
+            "detail-type": "AWS API Call via CloudTrail",
+            "source": "aws.iam",
+            "detail": {
+                "eventSource": "iam.amazonaws.com",
+                "eventName": event_name,
+                "userIdentity": {"type": "AssumedRole", "arn": arn},
+                "requestParameters": parameters,
+            },
+        })).expect("json parse error")
+    }
+
+    #[test]
+    pub fn test_credential_change_from_event() {
+        let e = event("ResetServiceSpecificCredential", serde_json::json!({"serviceSpecificCredentialId": "ABC", "userName": "bob"}), "arn:aws:iam::123456789012:user/alice");
+
+        assert_eq!(CredentialChange::from_event(&e, Some("rotator")), Some(CredentialChange {
+            event_name: "ResetServiceSpecificCredential".to_string(),
+            credential_id: "ABC".to_string(),
+            status: None,
+            changed_by: Some("arn:aws:iam::123456789012:user/alice".to_string()),
+        }));
+
+        let e = event("ResetServiceSpecificCredential", serde_json::json!({"serviceSpecificCredentialId": "ABC"}), "arn:aws:sts::123456789012:assumed-role/rotator-role/rotator");
+        assert_eq!(CredentialChange::from_event(&e, Some("rotator")), None);
+
+        let e = event("ListServiceSpecificCredentials", serde_json::json!({"userName": "bob"}), "arn:aws:iam::123456789012:user/alice");
+        assert_eq!(CredentialChange::from_event(&e, Some("rotator")), None);
+    }
+
+    #[test]
+    pub fn test_makes_stale() {
+        let e = event("UpdateServiceSpecificCredential", serde_json::json!({"serviceSpecificCredentialId": "ABC", "status": "Inactive"}), "arn:aws:iam::123456789012:user/alice");
+        assert!(CredentialChange::from_event(&e, None).unwrap().makes_stale());
+
+        let e = event("UpdateServiceSpecificCredential", serde_json::json!({"serviceSpecificCredentialId": "ABC", "status": "Active"}), "arn:aws:iam::123456789012:user/alice");
+        assert!(!CredentialChange::from_event(&e, None).unwrap().makes_stale());
+
+        let e = event("DeleteServiceSpecificCredential", serde_json::json!({"serviceSpecificCredentialId": "ABC"}), "arn:aws:iam::123456789012:user/alice");
+        assert!(CredentialChange::from_event(&e, None).unwrap().makes_stale());
+    }
+}
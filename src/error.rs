@@ -1,18 +1,67 @@
 use std::fmt;
 use serde::Serialize;
-use lambda_runtime::error::{LambdaErrorExt};
+use aws_sdk_secretsmanager::config::http::HttpResponse;
+use aws_sdk_secretsmanager::error::{ProvideErrorMetadata, SdkError};
+use lambda_runtime::Diagnostic;
+use crate::iam::IamError;
 
-#[derive(Clone, Debug, Serialize, LambdaErrorExt)]
+/// Whether retrying the step might succeed.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub enum ErrorClass {
+    Retryable,
+    Fatal,
+}
+
+/// Variant payloads may hold raw AWS SDK debug output, which is only logged.
+/// `Display` and the lambda error response use the sanitized `message` instead.
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum RotatorError {
     DescribeSecretError(String),
+    ListSecrets(String),
+    RotateSecret(String),
+    Lease(String),
     GetSecretValue(String),
     GetRandomPassword(String),
     PutSecretValue(String),
     UpdateSecretVersionStage(String),
+    TagResource(String),
+    Throttled {
+        api: String,
+        message: String,
+    },
+    ServiceUnavailable {
+        api: String,
+        message: String,
+    },
+    AccessDenied {
+        api: String,
+        message: String,
+    },
+    DeadlineExceeded {
+        secret_id: String,
+        step: String,
+    },
+    LeaseHeld {
+        secret_id: String,
+        holder: Option<String>,
+        expires_at: Option<String>,
+    },
     IamError {
         secret_id: String,
         message: String,
     },
+    IamEntityNotFound {
+        secret_id: String,
+        message: String,
+    },
+    IamLimitExceeded {
+        secret_id: String,
+        message: String,
+    },
+    IamServiceNotSupported {
+        secret_id: String,
+        message: String,
+    },
     InvalidConfig {
         secret_id: String,
         message: String,
@@ -30,6 +79,26 @@ pub enum RotatorError {
     RotationNotEnabled {
         secret_id: String,
     },
+    RotationDeclined {
+        secret_id: String,
+        step: String,
+        reason: String,
+    },
+    RollbackUnavailable {
+        secret_id: String,
+        reason: String,
+    },
+    ReplicaSecret {
+        secret_id: String,
+        region: String,
+        primary_region: String,
+    },
+    ReplicationIncomplete {
+        secret_id: String,
+        version: String,
+        regions: Vec<String>,
+        failed: bool,
+    },
     NoStageForRotation {
         secret_id: String,
         version: String,
@@ -49,21 +118,328 @@ pub enum RotatorError {
         secret_id: String,
         message: String,
     },
-    MissingTags {
+    UnsupportedSchemaVersion {
         secret_id: String,
+        version: u32,
     },
-    MissingTag {
+    InvalidTags {
         secret_id: String,
-        tag_name: String,
+        missing: Vec<String>,
+        invalid: Vec<String>,
+    },
+    UnmanagedCredentials {
+        secret_id: String,
+        user_name: String,
+        credential_ids: Vec<String>,
+        message: String,
+    },
+    InvalidPolicy {
+        message: String,
+    },
+    InvalidEvent {
+        message: String,
+    },
+    PolicyViolation {
+        secret_id: String,
+        rule: String,
+        value: String,
     },
     Other {
         message: String,
     },
 }
 
+impl RotatorError {
+    /// The variant name, for use as a metric dimension.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RotatorError::DescribeSecretError(_) => "DescribeSecretError",
+            RotatorError::ListSecrets(_) => "ListSecrets",
+            RotatorError::RotateSecret(_) => "RotateSecret",
+            RotatorError::Lease(_) => "Lease",
+            RotatorError::GetSecretValue(_) => "GetSecretValue",
+            RotatorError::GetRandomPassword(_) => "GetRandomPassword",
+            RotatorError::PutSecretValue(_) => "PutSecretValue",
+            RotatorError::UpdateSecretVersionStage(_) => "UpdateSecretVersionStage",
+            RotatorError::TagResource(_) => "TagResource",
+            RotatorError::Throttled { .. } => "Throttled",
+            RotatorError::ServiceUnavailable { .. } => "ServiceUnavailable",
+            RotatorError::AccessDenied { .. } => "AccessDenied",
+            RotatorError::DeadlineExceeded { .. } => "DeadlineExceeded",
+            RotatorError::LeaseHeld { .. } => "LeaseHeld",
+            RotatorError::IamError { .. } => "IamError",
+            RotatorError::IamEntityNotFound { .. } => "IamEntityNotFound",
+            RotatorError::IamLimitExceeded { .. } => "IamLimitExceeded",
+            RotatorError::IamServiceNotSupported { .. } => "IamServiceNotSupported",
+            RotatorError::InvalidConfig { .. } => "InvalidConfig",
+            RotatorError::SecretNotFound { .. } => "SecretNotFound",
+            RotatorError::SecretValueNotFound { .. } => "SecretValueNotFound",
+            RotatorError::RotationNotEnabled { .. } => "RotationNotEnabled",
+            RotatorError::RotationDeclined { .. } => "RotationDeclined",
+            RotatorError::RollbackUnavailable { .. } => "RollbackUnavailable",
+            RotatorError::ReplicaSecret { .. } => "ReplicaSecret",
+            RotatorError::ReplicationIncomplete { .. } => "ReplicationIncomplete",
+            RotatorError::NoStageForRotation { .. } => "NoStageForRotation",
+            RotatorError::NotSetAsPending { .. } => "NotSetAsPending",
+            RotatorError::InvalidPasswordParameter { .. } => "InvalidPasswordParameter",
+            RotatorError::EncryptionFailure { .. } => "EncryptionFailure",
+            RotatorError::SerialisationError { .. } => "SerialisationError",
+            RotatorError::UnsupportedSchemaVersion { .. } => "UnsupportedSchemaVersion",
+            RotatorError::InvalidTags { .. } => "InvalidTags",
+            RotatorError::UnmanagedCredentials { .. } => "UnmanagedCredentials",
+            RotatorError::InvalidPolicy { .. } => "InvalidPolicy",
+            RotatorError::InvalidEvent { .. } => "InvalidEvent",
+            RotatorError::PolicyViolation { .. } => "PolicyViolation",
+            RotatorError::Other { .. } => "Other",
+        }
+    }
+
+    /// A short suggestion for the operator, included in failure notifications.
+    pub fn remediation(&self) -> &'static str {
+        match self {
+            RotatorError::DescribeSecretError(_)
+            | RotatorError::ListSecrets(_)
+            | RotatorError::RotateSecret(_)
+            | RotatorError::GetSecretValue(_)
+            | RotatorError::GetRandomPassword(_)
+            | RotatorError::PutSecretValue(_)
+            | RotatorError::UpdateSecretVersionStage(_)
+            | RotatorError::TagResource(_) => "Check the lambda's Secrets Manager permissions; Secrets Manager will retry the step.",
+            RotatorError::Throttled { .. } | RotatorError::ServiceUnavailable { .. } => "Secrets Manager will retry the step; check AWS service health if it keeps failing.",
+            RotatorError::AccessDenied { .. } => "Grant the lambda's role permission to call the API.",
+            RotatorError::DeadlineExceeded { .. } => "Secrets Manager will retry the step; raise the lambda timeout if it keeps failing.",
+            RotatorError::Lease(_) => "Check the lambda's access to the lease table or secret tags; Secrets Manager will retry the step.",
+            RotatorError::LeaseHeld { .. } => "Another rotation of the secret is running; retry after it finishes or the lease expires.",
+            RotatorError::IamError { .. } => "Check the lambda's IAM permissions and that the IAM user exists.",
+            RotatorError::IamEntityNotFound { .. } => "Check the IAM user named in the secret's config exists.",
+            RotatorError::IamLimitExceeded { .. } => "Delete one of the user's credentials for the service; IAM allows two per user and service.",
+            RotatorError::IamServiceNotSupported { .. } => "Set rotator:serviceName to a service that supports service specific credentials.",
+            RotatorError::InvalidConfig { .. } => "Fix the rotator configuration in the secret's tags or value.",
+            RotatorError::SecretNotFound { .. } => "Check the secret exists and the lambda may describe it.",
+            RotatorError::SecretValueNotFound { .. } => "Check the secret's version stages; an earlier step may not have completed.",
+            RotatorError::RotationNotEnabled { .. } => "Enable rotation on the secret.",
+            RotatorError::RotationDeclined { .. } => "Secrets Manager will retry the rotation; it goes ahead once the rotation window is open.",
+            RotatorError::RollbackUnavailable { .. } => "Restore an earlier version by hand, or rotate the secret again.",
+            RotatorError::ReplicaSecret { .. } => "Rotate the secret in its primary region; replication copies the new version to the replicas.",
+            RotatorError::ReplicationIncomplete { .. } => "Check the secret's replication status; remove or recreate failed replicas, and Secrets Manager will retry the step.",
+            RotatorError::NoStageForRotation { .. } | RotatorError::NotSetAsPending { .. } => "Cancel the rotation and start it again.",
+            RotatorError::InvalidPasswordParameter { .. } => "Fix the password generation parameters.",
+            RotatorError::EncryptionFailure { .. } => "Check the lambda may use the secret's KMS key.",
+            RotatorError::SerialisationError { .. } => "Check the secret value is stored in its rotator:valueFormat and has the fields its resource type needs.",
+            RotatorError::UnsupportedSchemaVersion { .. } => "Deploy a rotator version that understands the secret value's rotatorSchemaVersion.",
+            RotatorError::InvalidTags { .. } => "Add the missing rotator: tags and fix the invalid ones.",
+            RotatorError::UnmanagedCredentials { .. } => "Delete the unmanaged credentials or tag the secret with rotator:adoptionMode=Adopt.",
+            RotatorError::InvalidPolicy { .. } => "Fix the ROTATOR_POLICY document in the lambda environment.",
+            RotatorError::InvalidEvent { .. } => "Check what is invoking the lambda; see the README for the events it accepts.",
+            RotatorError::PolicyViolation { .. } => "Point the secret at a permitted resource or update the rotation policy.",
+            RotatorError::Other { .. } => "See the lambda logs for details.",
+        }
+    }
+
+    /// A stable identifier for the error, returned as the lambda `errorType`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RotatorError::DescribeSecretError(_) => "DESCRIBE_SECRET_FAILED",
+            RotatorError::ListSecrets(_) => "LIST_SECRETS_FAILED",
+            RotatorError::RotateSecret(_) => "ROTATE_SECRET_FAILED",
+            RotatorError::Lease(_) => "LEASE_FAILED",
+            RotatorError::GetSecretValue(_) => "GET_SECRET_VALUE_FAILED",
+            RotatorError::GetRandomPassword(_) => "GET_RANDOM_PASSWORD_FAILED",
+            RotatorError::PutSecretValue(_) => "PUT_SECRET_VALUE_FAILED",
+            RotatorError::UpdateSecretVersionStage(_) => "UPDATE_SECRET_VERSION_STAGE_FAILED",
+            RotatorError::TagResource(_) => "TAG_RESOURCE_FAILED",
+            RotatorError::Throttled { .. } => "THROTTLED",
+            RotatorError::ServiceUnavailable { .. } => "SERVICE_UNAVAILABLE",
+            RotatorError::AccessDenied { .. } => "ACCESS_DENIED",
+            RotatorError::DeadlineExceeded { .. } => "DEADLINE_EXCEEDED",
+            RotatorError::LeaseHeld { .. } => "LEASE_HELD",
+            RotatorError::IamError { .. } => "IAM_REQUEST_FAILED",
+            RotatorError::IamEntityNotFound { .. } => "IAM_ENTITY_NOT_FOUND",
+            RotatorError::IamLimitExceeded { .. } => "IAM_LIMIT_EXCEEDED",
+            RotatorError::IamServiceNotSupported { .. } => "IAM_SERVICE_NOT_SUPPORTED",
+            RotatorError::InvalidConfig { .. } => "INVALID_CONFIG",
+            RotatorError::SecretNotFound { .. } => "SECRET_NOT_FOUND",
+            RotatorError::SecretValueNotFound { .. } => "SECRET_VALUE_NOT_FOUND",
+            RotatorError::RotationNotEnabled { .. } => "ROTATION_NOT_ENABLED",
+            RotatorError::RotationDeclined { .. } => "ROTATION_DECLINED",
+            RotatorError::RollbackUnavailable { .. } => "ROLLBACK_UNAVAILABLE",
+            RotatorError::ReplicaSecret { .. } => "REPLICA_SECRET",
+            RotatorError::ReplicationIncomplete { .. } => "REPLICATION_INCOMPLETE",
+            RotatorError::NoStageForRotation { .. } => "NO_STAGE_FOR_ROTATION",
+            RotatorError::NotSetAsPending { .. } => "NOT_SET_AS_PENDING",
+            RotatorError::InvalidPasswordParameter { .. } => "INVALID_PASSWORD_PARAMETER",
+            RotatorError::EncryptionFailure { .. } => "ENCRYPTION_FAILURE",
+            RotatorError::SerialisationError { .. } => "SERIALISATION_ERROR",
+            RotatorError::UnsupportedSchemaVersion { .. } => "UNSUPPORTED_SCHEMA_VERSION",
+            RotatorError::InvalidTags { .. } => "INVALID_TAGS",
+            RotatorError::UnmanagedCredentials { .. } => "UNMANAGED_CREDENTIALS",
+            RotatorError::InvalidPolicy { .. } => "INVALID_POLICY",
+            RotatorError::InvalidEvent { .. } => "INVALID_EVENT",
+            RotatorError::PolicyViolation { .. } => "POLICY_VIOLATION",
+            RotatorError::Other { .. } => "INTERNAL_ERROR",
+        }
+    }
+
+    /// Unclassified AWS failures are assumed to be transient.
+    ///
+    /// Every variant is listed, so that a new one has to be classified.
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            RotatorError::DescribeSecretError(_)
+            | RotatorError::ListSecrets(_)
+            | RotatorError::RotateSecret(_)
+            | RotatorError::GetSecretValue(_)
+            | RotatorError::GetRandomPassword(_)
+            | RotatorError::PutSecretValue(_)
+            | RotatorError::UpdateSecretVersionStage(_)
+            | RotatorError::TagResource(_)
+            | RotatorError::Throttled { .. }
+            | RotatorError::ServiceUnavailable { .. }
+            | RotatorError::DeadlineExceeded { .. }
+            | RotatorError::Lease(_)
+            | RotatorError::LeaseHeld { .. }
+            | RotatorError::RotationDeclined { .. }
+            | RotatorError::ReplicationIncomplete { failed: false, .. }
+            | RotatorError::IamError { .. } => ErrorClass::Retryable,
+            RotatorError::AccessDenied { .. }
+            | RotatorError::IamEntityNotFound { .. }
+            | RotatorError::IamLimitExceeded { .. }
+            | RotatorError::IamServiceNotSupported { .. }
+            | RotatorError::InvalidConfig { .. }
+            | RotatorError::SecretNotFound { .. }
+            | RotatorError::SecretValueNotFound { .. }
+            | RotatorError::RotationNotEnabled { .. }
+            | RotatorError::RollbackUnavailable { .. }
+            | RotatorError::ReplicaSecret { .. }
+            | RotatorError::ReplicationIncomplete { failed: true, .. }
+            | RotatorError::NoStageForRotation { .. }
+            | RotatorError::NotSetAsPending { .. }
+            | RotatorError::InvalidPasswordParameter { .. }
+            | RotatorError::EncryptionFailure { .. }
+            | RotatorError::SerialisationError { .. }
+            | RotatorError::UnsupportedSchemaVersion { .. }
+            | RotatorError::InvalidTags { .. }
+            | RotatorError::UnmanagedCredentials { .. }
+            | RotatorError::InvalidPolicy { .. }
+            | RotatorError::InvalidEvent { .. }
+            | RotatorError::PolicyViolation { .. }
+            | RotatorError::Other { .. } => ErrorClass::Fatal,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.class() == ErrorClass::Retryable
+    }
+
+    /// A description that is safe to return to callers, without SDK debug output or secret values.
+    pub fn message(&self) -> String {
+        match self {
+            RotatorError::DescribeSecretError(_) => "Secrets Manager DescribeSecret request failed".to_string(),
+            RotatorError::ListSecrets(_) => "Secrets Manager ListSecrets request failed".to_string(),
+            RotatorError::RotateSecret(_) => "Secrets Manager RotateSecret request failed".to_string(),
+            RotatorError::Lease(_) => "rotation lease request failed".to_string(),
+            RotatorError::GetSecretValue(_) => "Secrets Manager GetSecretValue request failed".to_string(),
+            RotatorError::GetRandomPassword(_) => "Secrets Manager GetRandomPassword request failed".to_string(),
+            RotatorError::PutSecretValue(_) => "Secrets Manager PutSecretValue request failed".to_string(),
+            RotatorError::UpdateSecretVersionStage(_) => "Secrets Manager UpdateSecretVersionStage request failed".to_string(),
+            RotatorError::TagResource(_) => "Secrets Manager TagResource request failed".to_string(),
+            RotatorError::Throttled { api, .. } => format!("{} request was throttled", api),
+            RotatorError::ServiceUnavailable { api, .. } => format!("{} request did not complete", api),
+            RotatorError::AccessDenied { api, .. } => format!("access denied calling {}", api),
+            RotatorError::DeadlineExceeded { secret_id, step } => format!("{} for secret {} did not finish before the lambda deadline", step, secret_id),
+            RotatorError::LeaseHeld { secret_id, expires_at, .. } => match expires_at {
+                Some(expires_at) => format!("secret {} is being rotated by another request until {}", secret_id, expires_at),
+                None => format!("secret {} is being rotated by another request", secret_id),
+            },
+            RotatorError::IamError { secret_id, .. } => format!("IAM request failed for secret {}", secret_id),
+            RotatorError::IamEntityNotFound { secret_id, .. } => format!("IAM user or credential not found for secret {}", secret_id),
+            RotatorError::IamLimitExceeded { secret_id, .. } => format!("IAM user for secret {} has no room for another credential", secret_id),
+            RotatorError::IamServiceNotSupported { secret_id, .. } => format!("IAM service for secret {} doesn't support service specific credentials", secret_id),
+            RotatorError::InvalidConfig { secret_id, message } => format!("invalid config for secret {}: {}", secret_id, message),
+            RotatorError::SecretNotFound { secret_id, .. } => format!("secret {} not found", secret_id),
+            RotatorError::SecretValueNotFound { secret_id, version_stage, version_ids, .. } => {
+                format!("secret {} has no value for stage {} and versions [{}]", secret_id, version_stage.as_deref().unwrap_or("none"), version_ids.join(", "))
+            },
+            RotatorError::RotationNotEnabled { secret_id } => format!("rotation is not enabled for secret {}", secret_id),
+            RotatorError::RotationDeclined { secret_id, step, reason } => format!("{} for secret {} declined: {}", step, secret_id, reason),
+            RotatorError::RollbackUnavailable { secret_id, reason } => format!("secret {} can't be rolled back: {}", secret_id, reason),
+            RotatorError::ReplicaSecret { secret_id, region, primary_region } => format!("secret {} is a replica in {} of a secret in {}", secret_id, region, primary_region),
+            RotatorError::ReplicationIncomplete { secret_id, version, regions, failed } => if *failed {
+                format!("replicas of secret {} in [{}] failed and won't get version {}", secret_id, regions.join(", "), version)
+            } else {
+                format!("replicas of secret {} in [{}] don't have version {} yet", secret_id, regions.join(", "), version)
+            },
+            RotatorError::NoStageForRotation { secret_id, version } => format!("version {} of secret {} has no stage for rotation", version, secret_id),
+            RotatorError::NotSetAsPending { secret_id, version } => format!("version {} of secret {} is not AWSPENDING", version, secret_id),
+            RotatorError::InvalidPasswordParameter { .. } => "invalid password generation parameters".to_string(),
+            RotatorError::EncryptionFailure { secret_id, .. } => format!("could not encrypt secret {}", secret_id),
+            RotatorError::SerialisationError { secret_id, .. } => format!("value of secret {} is not in the expected format", secret_id),
+            RotatorError::UnsupportedSchemaVersion { secret_id, version } => format!("value of secret {} has schema version {}, which is newer than this rotator supports", secret_id, version),
+            RotatorError::InvalidTags { secret_id, missing, invalid } => {
+                format!("invalid config for secret {}: missing [{}] invalid [{}]", secret_id, missing.join(", "), invalid.join(", "))
+            },
+            RotatorError::UnmanagedCredentials { secret_id, user_name, credential_ids, .. } => {
+                format!("user {} for secret {} has unmanaged credentials [{}]", user_name, secret_id, credential_ids.join(", "))
+            },
+            RotatorError::InvalidPolicy { .. } => "the rotation policy document is invalid".to_string(),
+            RotatorError::InvalidEvent { message } => message.to_string(),
+            RotatorError::PolicyViolation { secret_id, rule, value } => format!("{} {} for secret {} is not permitted by policy", rule, value, secret_id),
+            RotatorError::Other { message } => message.to_string(),
+        }
+    }
+
+    /// Maps failures that any AWS API can return, or returns `None` for the caller to handle.
+    pub fn from_sdk<E: ProvideErrorMetadata + fmt::Debug>(api: &str, err: &SdkError<E, HttpResponse>) -> Option<RotatorError> {
+        let message = format!("{:?}", err);
+
+        if let SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) = err {
+            return Some(RotatorError::ServiceUnavailable { api: api.to_string(), message });
+        }
+
+        let status = err.raw_response().map(|response| response.status());
+
+        match err.code() {
+            Some("Throttling") | Some("ThrottlingException") | Some("TooManyRequestsException") => {
+                Some(RotatorError::Throttled { api: api.to_string(), message })
+            },
+            Some("AccessDenied") | Some("AccessDeniedException") => {
+                Some(RotatorError::AccessDenied { api: api.to_string(), message })
+            },
+            _ if status.map(|status| status.as_u16() == 429).unwrap_or(false) => {
+                Some(RotatorError::Throttled { api: api.to_string(), message })
+            },
+            _ if status.map(|status| status.is_server_error()).unwrap_or(false) => {
+                Some(RotatorError::ServiceUnavailable { api: api.to_string(), message })
+            },
+            _ => None,
+        }
+    }
+
+    pub fn from_iam(secret_id: &str, context: &str, err: IamError) -> RotatorError {
+        let message = format!("{}: {:?}", context, err);
+
+        match err {
+            IamError::EntityNotFound { .. } => RotatorError::IamEntityNotFound { secret_id: secret_id.to_string(), message },
+            IamError::LimitExceeded { .. } => RotatorError::IamLimitExceeded { secret_id: secret_id.to_string(), message },
+            IamError::ServiceNotSupported { .. } => RotatorError::IamServiceNotSupported { secret_id: secret_id.to_string(), message },
+            IamError::Aws(err) => err,
+            _ => RotatorError::IamError { secret_id: secret_id.to_string(), message },
+        }
+    }
+}
+
+impl From<RotatorError> for Diagnostic {
+    fn from(err: RotatorError) -> Diagnostic {
+        Diagnostic {
+            error_type: err.code().to_string(),
+            error_message: err.message(),
+        }
+    }
+}
+
 impl fmt::Display for RotatorError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        write!(f, "{}", self.message())
     }
 }
 
@@ -71,3 +447,152 @@ impl std::error::Error for RotatorError {
 }
 
 pub type RotatorResult<R> = std::result::Result<R,RotatorError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use aws_sdk_secretsmanager::operation::describe_secret::DescribeSecretError;
+
+    #[test]
+    pub fn test_message_excludes_sdk_output() {
+        let err = RotatorError::SerialisationError {
+            secret_id: "secret".to_string(),
+            message: "invalid type: string \"hunter2\"".to_string(),
+        };
+
+        assert_eq!(err.code(), "SERIALISATION_ERROR");
+        assert_eq!(err.class(), ErrorClass::Fatal);
+        assert_eq!(format!("{}", err), "value of secret secret is not in the expected format");
+
+        let diagnostic = Diagnostic::from(err);
+        assert_eq!(diagnostic.error_type, "SERIALISATION_ERROR");
+        assert_eq!(diagnostic.error_message, "value of secret secret is not in the expected format");
+    }
+
+    #[test]
+    pub fn test_sdk_errors() {
+        let err: SdkError<DescribeSecretError, HttpResponse> = SdkError::timeout_error("timed out");
+
+        let mapped = RotatorError::from_sdk("DescribeSecret", &err).expect("unmapped error");
+        assert_eq!(mapped.code(), "SERVICE_UNAVAILABLE");
+        assert!(mapped.is_retryable());
+        assert_eq!(format!("{}", mapped), "DescribeSecret request did not complete");
+
+        let err: SdkError<DescribeSecretError, HttpResponse> = SdkError::construction_failure("bad");
+        assert_eq!(RotatorError::from_sdk("DescribeSecret", &err), None);
+    }
+
+    #[test]
+    pub fn test_iam_errors() {
+        let err = RotatorError::from_iam("secret", "list service specific credential error", IamError::EntityNotFound {
+            message: "The user with name Bob cannot be found.".to_string(),
+        });
+
+        assert_eq!(err.code(), "IAM_ENTITY_NOT_FOUND");
+        assert_eq!(err.class(), ErrorClass::Fatal);
+
+        let err = RotatorError::from_iam("secret", "reset service specific credential error", IamError::Aws(RotatorError::Throttled {
+            api: "ResetServiceSpecificCredential".to_string(),
+            message: "Throttling".to_string(),
+        }));
+
+        assert_eq!(err.code(), "THROTTLED");
+        assert!(err.is_retryable());
+
+        let err = RotatorError::from_iam("secret", "create service specific credential error", IamError::LimitExceeded {
+            message: "Cannot exceed quota for ServiceSpecificCredentialsPerUserPerService: 2".to_string(),
+        });
+
+        assert_eq!(err.code(), "IAM_LIMIT_EXCEEDED");
+        assert_eq!(err.class(), ErrorClass::Fatal);
+
+        let err = RotatorError::from_iam("secret", "list service specific credential error", IamError::ServiceNotSupported {
+            message: "not supported".to_string(),
+        });
+
+        assert_eq!(err.code(), "IAM_SERVICE_NOT_SUPPORTED");
+        assert_eq!(err.class(), ErrorClass::Fatal);
+
+        let err = RotatorError::from_iam("secret", "reset service specific credential error", IamError::Aws(RotatorError::AccessDenied {
+            api: "ResetServiceSpecificCredential".to_string(),
+            message: "AccessDenied".to_string(),
+        }));
+
+        assert_eq!(err.code(), "ACCESS_DENIED");
+        assert_eq!(err.class(), ErrorClass::Fatal);
+
+        let err = RotatorError::from_iam("secret", "reset service specific credential error", IamError::MissingCredentialInResponse);
+
+        assert_eq!(err.code(), "IAM_REQUEST_FAILED");
+        assert!(err.is_retryable());
+    }
+
+    /// Builds `every_variant` from the variant names and their fields, which are given default values.
+    /// The match has no wildcard arm, so a new variant fails to compile until it's listed here.
+    macro_rules! every_variant {
+        ($($name:ident { $($field:tt),* }),* $(,)?) => {
+            fn every_variant() -> Vec<RotatorError> {
+                let errors = vec![$(RotatorError::$name { $($field: Default::default()),* }),*];
+                for err in errors.iter() {
+                    match err {
+                        $(RotatorError::$name { .. } => (),)*
+                    }
+                }
+                errors
+            }
+        };
+    }
+
+    every_variant! {
+        DescribeSecretError { 0 },
+        ListSecrets { 0 },
+        RotateSecret { 0 },
+        Lease { 0 },
+        GetSecretValue { 0 },
+        GetRandomPassword { 0 },
+        PutSecretValue { 0 },
+        UpdateSecretVersionStage { 0 },
+        TagResource { 0 },
+        Throttled { api, message },
+        ServiceUnavailable { api, message },
+        AccessDenied { api, message },
+        DeadlineExceeded { secret_id, step },
+        LeaseHeld { secret_id, holder, expires_at },
+        IamError { secret_id, message },
+        IamEntityNotFound { secret_id, message },
+        IamLimitExceeded { secret_id, message },
+        IamServiceNotSupported { secret_id, message },
+        InvalidConfig { secret_id, message },
+        SecretNotFound { secret_id, message },
+        SecretValueNotFound { secret_id, version_stage, version_ids, message },
+        RotationNotEnabled { secret_id },
+        RotationDeclined { secret_id, step, reason },
+        RollbackUnavailable { secret_id, reason },
+        ReplicaSecret { secret_id, region, primary_region },
+        ReplicationIncomplete { secret_id, version, regions, failed },
+        NoStageForRotation { secret_id, version },
+        NotSetAsPending { secret_id, version },
+        InvalidPasswordParameter { message },
+        EncryptionFailure { secret_id, message },
+        SerialisationError { secret_id, message },
+        UnsupportedSchemaVersion { secret_id, version },
+        InvalidTags { secret_id, missing, invalid },
+        UnmanagedCredentials { secret_id, user_name, credential_ids, message },
+        InvalidPolicy { message },
+        InvalidEvent { message },
+        PolicyViolation { secret_id, rule, value },
+        Other { message },
+    }
+
+    #[test]
+    pub fn test_codes_are_unique() {
+        let errors = every_variant();
+
+        let codes: BTreeSet<&str> = errors.iter().map(|err| err.code()).collect();
+        assert_eq!(codes.len(), errors.len());
+
+        let names: BTreeSet<&str> = errors.iter().map(|err| err.name()).collect();
+        assert_eq!(names.len(), errors.len());
+    }
+}
@@ -1,64 +1,96 @@
-use std::time::Duration;
-use rusoto_core::RusotoError;
-use rusoto_secretsmanager::{
-    SecretsManager,
-    UpdateSecretVersionStageRequest,
-    UpdateSecretVersionStageResponse,
-    UpdateSecretVersionStageError,
-};
+use aws_sdk_secretsmanager::operation::update_secret_version_stage::UpdateSecretVersionStageOutput;
 use crate::{
     RotatorError,
     RotatorEvent,
     RotatorResult,
+    StepOutcome,
     SM_CLIENT,
 };
+use crate::metrics::time_api_call;
 use crate::describe_secret;
-use std::collections::HashMap;
+use crate::audit::{AuditRecord, AuditSinks, PENDING_CHANGES_TAG, pending_changes};
+use crate::config::Config;
+use crate::stage::{StageMove, VersionStages, AWSCURRENT, AWSPREVIOUS};
+use crate::value::get_secret_value;
+use chrono::Utc;
 
-fn update_secret_version_stage(secret_id: &str, version_stage: &str, new_version: &str, old_version: Option<String>, timeout: Duration) -> RotatorResult<UpdateSecretVersionStageResponse> {
-    info!("updating secret version stage secret_id={} version_stage={} new_version={} old_version={:?}", secret_id, version_stage, new_version, old_version);
+pub async fn update_secret_version_stage(secret_id: &str, stage_move: &StageMove) -> RotatorResult<UpdateSecretVersionStageOutput> {
+    info!("updating secret version stage secret_id={} version_stage={} new_version={} old_version={:?}", secret_id, stage_move.label, stage_move.to_version, stage_move.from_version);
 
-    SM_CLIENT.update_secret_version_stage(UpdateSecretVersionStageRequest {
-        secret_id: secret_id.to_string(),
-        version_stage: "AWSCURRENT".to_string(),
-        move_to_version_id: Some(new_version.to_string()),
-        remove_from_version_id: old_version.clone(),
-    })
-        .with_timeout(timeout)
-        .sync()
-        .map_err(|e| match e {
-            RusotoError::Service(UpdateSecretVersionStageError::ResourceNotFound(msg)) => {
-                let mut version_ids = old_version.map(|s| vec![s.to_string()]).unwrap_or(vec![]);
-                version_ids.push(new_version.to_string());
+    time_api_call("UpdateSecretVersionStage", SM_CLIENT.update_secret_version_stage()
+        .secret_id(secret_id)
+        .version_stage(&stage_move.label)
+        .move_to_version_id(&stage_move.to_version)
+        .set_remove_from_version_id(stage_move.from_version.clone())
+        .send())
+        .await
+        .map_err(|e| match e.as_service_error() {
+            Some(err) if err.is_resource_not_found_exception() => {
+                let mut version_ids: Vec<String> = stage_move.from_version.iter().cloned().collect();
+                version_ids.push(stage_move.to_version.to_string());
                 RotatorError::SecretValueNotFound {
                     secret_id: secret_id.to_string(),
-                    version_stage: Some(version_stage.to_string()),
-                    version_ids: version_ids,
-                    message: msg
+                    version_stage: Some(stage_move.label.to_string()),
+                    version_ids,
+                    message: err.meta().message().unwrap_or_default().to_string(),
                 }
             },
-            e => RotatorError::UpdateSecretVersionStage(format!("{:?}", e))
+            _ => RotatorError::from_sdk("UpdateSecretVersionStage", &e).unwrap_or_else(|| RotatorError::UpdateSecretVersionStage(format!("{:?}", e)))
         })
 }
 
-pub fn finish_secret(e: RotatorEvent) -> RotatorResult<()> {
-    let timeout = Duration::from_secs(2);
+pub async fn finish_secret(e: RotatorEvent, config: &Config) -> RotatorResult<StepOutcome> {
+    let secret = describe_secret(&e.secret_id).await?;
+    let stages = VersionStages::from_secret(&secret);
+    let current_version = stages.current().map(|v| v.to_string());
+    let moves = stages.promote(&e.client_request_token, &config.finish_labels);
+
+    if moves.is_empty() {
+        info!("finishSecret: Version {:?} already marked as AWSCURRENT for {}", e.client_request_token, e.secret_id);
+        return Ok(StepOutcome::no_op(vec![e.client_request_token.to_string()]));
+    }
+
+    let pending = get_secret_value(&e.secret_id, config.value_format, None, Some(&e.client_request_token)).await?;
+
+    for stage_move in moves.iter() {
+        update_secret_version_stage(&e.secret_id, stage_move).await?;
+        info!("finishSecret: Successfully set {} stage to version {:?} for secret {}.", stage_move.label, e.client_request_token, e.secret_id);
+    }
+
+    info!("finishSecret: {} is now on version {:?} for secret {}", AWSPREVIOUS, stages.apply(&moves).previous(), e.secret_id);
+
+    let changes = secret.tags().iter()
+        .find(|tag| tag.key() == Some(PENDING_CHANGES_TAG))
+        .and_then(|tag| tag.value())
+        .map(|value| pending_changes(value, &e.client_request_token))
+        .unwrap_or_default();
+    AuditSinks::from_environment().write(&AuditRecord::finished(&e.secret_id, &e.client_request_token, current_version.as_deref(), &pending, changes, Utc::now())).await;
+
+    let mut versions = vec![e.client_request_token.to_string()];
+    versions.extend(current_version);
+
+    Ok(StepOutcome {
+        no_op: false,
+        changes: vec![],
+        versions,
+    })
+}
+
+pub async fn plan_finish_secret(e: &RotatorEvent, config: &Config) -> RotatorResult<Vec<String>> {
+    let stages = VersionStages::from_secret(&describe_secret(&e.secret_id).await?);
+    let moves = stages.promote(&e.client_request_token, &config.finish_labels);
 
-    let secret = describe_secret(&e.secret_id, timeout)?;
-    let mut current_version = None;
-    let version_ids_to_stages = secret.version_ids_to_stages.unwrap_or(HashMap::new());
-    for (version, stages) in version_ids_to_stages.iter() {
-        if stages.contains(&"AWSCURRENT".to_string()) {
-            if version == &e.client_request_token {
-                info!("finishSecret: Version {:?} already marked as AWSCURRENT for {}", version, e.secret_id);
-                return Ok(());
-            }
-            current_version = Some(version.to_string());
-        }
+    if moves.is_empty() {
+        return Ok(vec![format!("version {} already marked as {}, nothing to do", e.client_request_token, AWSCURRENT)]);
     }
 
-    update_secret_version_stage(&e.secret_id, "AWSCURRENT", &e.client_request_token, current_version, timeout)?;
-    info!("finishSecret: Successfully set AWSCURRENT stage to version {:?} for secret {}.", e.client_request_token, e.secret_id);
-    
-    Ok(())
-}
\ No newline at end of file
+    let mut plan: Vec<String> = moves.iter()
+        .map(|m| format!("would move {} from version {:?} to version {}", m.label, m.from_version, m.to_version))
+        .collect();
+
+    if let Some(previous) = stages.apply(&moves).previous() {
+        plan.push(format!("{} would then be on version {}", AWSPREVIOUS, previous));
+    }
+
+    Ok(plan)
+}
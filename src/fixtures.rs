@@ -0,0 +1,23 @@
+//! Values shared by the unit tests of several modules.
+
+use crate::iam::{CredentialMetadata, CredentialStatus};
+use crate::stage::VersionStages;
+
+/// A credential of bob's for CodeCommit.
+pub fn metadata(id: &str, status: CredentialStatus) -> CredentialMetadata {
+    CredentialMetadata {
+        create_date: "2026-01-01T00:00:00Z".to_string(),
+        service_name: "codecommit.amazonaws.com".to_string(),
+        service_specific_credential_id: id.to_string(),
+        service_user_name: "bob-at-123".to_string(),
+        status,
+        user_name: "bob".to_string(),
+    }
+}
+
+/// The staging labels of each version, as DescribeSecret lists them.
+pub fn stages(versions: &[(&str, &[&str])]) -> VersionStages {
+    VersionStages::new(versions.iter()
+        .map(|(version, labels)| (version.to_string(), labels.iter().map(|l| l.to_string()).collect()))
+        .collect())
+}
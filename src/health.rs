@@ -0,0 +1,62 @@
+use serde::Serialize;
+use crate::sdk_config;
+use crate::policy::Policy;
+
+const LAMBDA_VERSION_ENVIRONMENT_VARIABLE: &str = "AWS_LAMBDA_FUNCTION_VERSION";
+
+/// Whether the lambda is configured well enough to rotate, without calling AWS.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+    pub healthy: bool,
+    pub version: String,
+    pub lambda_version: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub problems: Vec<String>,
+}
+
+impl HealthReport {
+    pub fn new(problems: Vec<String>) -> Self {
+        HealthReport {
+            healthy: problems.is_empty(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            lambda_version: std::env::var(LAMBDA_VERSION_ENVIRONMENT_VARIABLE).ok(),
+            problems,
+        }
+    }
+}
+
+pub fn health_check() -> HealthReport {
+    let mut problems = vec![];
+
+    if let Err(err) = Policy::from_environment() {
+        problems.push(err.message());
+    }
+    if sdk_config().region().is_none() {
+        problems.push("no AWS region is configured".to_string());
+    }
+
+    HealthReport::new(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    pub fn test_health_report() {
+        let mut report = HealthReport::new(vec![]);
+        report.lambda_version = None;
+
+        assert_eq!(serde_json::to_value(&report).unwrap(), json!({
+            "healthy": true,
+            "version": env!("CARGO_PKG_VERSION"),
+            "lambdaVersion": null,
+        }));
+
+        let report = HealthReport::new(vec!["the rotation policy document is invalid".to_string()]);
+
+        assert!(!report.healthy);
+    }
+}
@@ -1,20 +1,15 @@
-use rusoto_core::RusotoError;
-use std::time::Duration;
 use std::fmt;
-use rusoto_iam::{
-    Iam,
-    ListServiceSpecificCredentialsRequest,
-    ListServiceSpecificCredentialsError,
+use aws_sdk_iam::config::http::HttpResponse;
+use aws_sdk_iam::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_iam::primitives::{DateTime, DateTimeFormat};
+use aws_sdk_iam::types::{
     ServiceSpecificCredential,
     ServiceSpecificCredentialMetadata,
-    CreateServiceSpecificCredentialRequest,
-    CreateServiceSpecificCredentialError,
-    ResetServiceSpecificCredentialRequest,
-    ResetServiceSpecificCredentialError,
-    UpdateServiceSpecificCredentialRequest,
-    UpdateServiceSpecificCredentialError,
+    StatusType,
 };
-use crate::IAM_CLIENT;
+use crate::{IAM_CLIENT, RotatorError};
+use crate::sensitive::Sensitive;
+use crate::metrics::time_api_call;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum CredentialStatus {
@@ -53,40 +48,50 @@ pub struct CredentialMetadata {
     pub user_name: String,
 }
 
-impl From<ServiceSpecificCredentialMetadata> for CredentialMetadata {
-    fn from(m: ServiceSpecificCredentialMetadata) -> CredentialMetadata {
+impl From<&StatusType> for CredentialStatus {
+    fn from(status: &StatusType) -> CredentialStatus {
+        CredentialStatus::from(status.as_str())
+    }
+}
+
+fn rfc3339(date: &DateTime) -> String {
+    date.fmt(DateTimeFormat::DateTime).unwrap_or_default()
+}
+
+impl From<&ServiceSpecificCredentialMetadata> for CredentialMetadata {
+    fn from(m: &ServiceSpecificCredentialMetadata) -> CredentialMetadata {
         CredentialMetadata {
-            create_date: m.create_date,
-            service_name: m.service_name,
-            service_specific_credential_id: m.service_specific_credential_id,
-            service_user_name: m.service_user_name,
-            status: CredentialStatus::from(&m.status[..]),
-            user_name: m.user_name,
+            create_date: rfc3339(m.create_date()),
+            service_name: m.service_name().to_string(),
+            service_specific_credential_id: m.service_specific_credential_id().to_string(),
+            service_user_name: m.service_user_name().to_string(),
+            status: CredentialStatus::from(m.status()),
+            user_name: m.user_name().to_string(),
         }
     }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Credential {
     pub create_date: String,
     pub service_name: String,
-    pub service_password: String,
+    pub service_password: Sensitive<String>,
     pub service_specific_credential_id: String,
     pub service_user_name: String,
     pub status: CredentialStatus,
     pub user_name: String,
 }
 
-impl From<ServiceSpecificCredential> for Credential {
-    fn from(m: ServiceSpecificCredential) -> Credential {
+impl From<&ServiceSpecificCredential> for Credential {
+    fn from(m: &ServiceSpecificCredential) -> Credential {
         Credential {
-            create_date: m.create_date,
-            service_name: m.service_name,
-            service_password: m.service_password,
-            service_specific_credential_id: m.service_specific_credential_id,
-            service_user_name: m.service_user_name,
-            status: CredentialStatus::from(&m.status[..]),
-            user_name: m.user_name,
+            create_date: rfc3339(m.create_date()),
+            service_name: m.service_name().to_string(),
+            service_password: Sensitive::new(m.service_password().to_string()),
+            service_specific_credential_id: m.service_specific_credential_id().to_string(),
+            service_user_name: m.service_user_name().to_string(),
+            status: CredentialStatus::from(m.status()),
+            user_name: m.user_name().to_string(),
         }
     }
 }
@@ -102,10 +107,12 @@ pub enum IamError {
     LimitExceeded {
         message: String,
     },
-    RusotoError {
+    SdkError {
         message: String,
     },
     MissingCredentialInResponse,
+    /// Throttling, access denied and similar failures common to every AWS API.
+    Aws(RotatorError),
 }
 
 impl fmt::Display for IamError {
@@ -119,110 +126,132 @@ impl std::error::Error for IamError {
 
 pub type IamResult<T> = std::result::Result<T, IamError>;
 
-pub fn list_service_specific_credentials(user_name: Option<&str>, service_name: Option<&str>, timeout: Duration) -> IamResult<Vec<CredentialMetadata>> {
+fn sdk_error<E: ProvideErrorMetadata + fmt::Debug>(api: &str, err: SdkError<E, HttpResponse>) -> IamError {
+    match RotatorError::from_sdk(api, &err) {
+        Some(err) => IamError::Aws(err),
+        None => IamError::SdkError {
+            message: format!("{:?}", err)
+        },
+    }
+}
+
+fn message<E: ProvideErrorMetadata>(err: &E) -> String {
+    err.message().unwrap_or_default().to_string()
+}
+
+pub async fn list_service_specific_credentials(user_name: Option<&str>, service_name: Option<&str>) -> IamResult<Vec<CredentialMetadata>> {
     info!("listing service specific credentials user_name={:?} service_name={:?}", user_name, service_name);
-    
-    IAM_CLIENT.list_service_specific_credentials(ListServiceSpecificCredentialsRequest {
-        service_name: service_name.map(|s| s.to_string()),
-        user_name: user_name.map(|s| s.to_string()),
-    })
-        .with_timeout(timeout)
-        .sync()
-        .map_err(|err| match err {
-            RusotoError::Service(ListServiceSpecificCredentialsError::NoSuchEntity(msg)) => {
+
+    time_api_call("ListServiceSpecificCredentials", IAM_CLIENT.list_service_specific_credentials()
+        .set_service_name(service_name.map(|s| s.to_string()))
+        .set_user_name(user_name.map(|s| s.to_string()))
+        .send())
+        .await
+        .map_err(|err| match err.as_service_error() {
+            Some(e) if e.is_no_such_entity_exception() => {
                 IamError::EntityNotFound {
-                    message: msg
+                    message: message(e)
                 }
             },
-            RusotoError::Service(ListServiceSpecificCredentialsError::ServiceNotSupported(msg)) => {
+            Some(e) if e.is_service_not_supported_exception() => {
                 IamError::ServiceNotSupported {
-                    message: msg
+                    message: message(e)
                 }
             },
-            err => IamError::RusotoError {
-                message: format!("{:?}", err)
-            }
+            _ => sdk_error("ListServiceSpecificCredentials", err)
         })
         .map(|creds| {
-            creds.service_specific_credentials.unwrap_or(vec![])
-                .into_iter()
-                .map(|metadata| CredentialMetadata::from(metadata))
+            creds.service_specific_credentials()
+                .iter()
+                .map(CredentialMetadata::from)
                 .collect()
         })
 }
 
-pub fn create_service_specific_credential(user_name: &str, service_name: &str, timeout: Duration) -> IamResult<Credential> {
-    let cred = IAM_CLIENT.create_service_specific_credential(CreateServiceSpecificCredentialRequest {
-        user_name: user_name.to_string(),
-        service_name: service_name.to_string(),
-    })
-        .with_timeout(timeout)
-        .sync()
-        .map_err(|err| match err {
-            RusotoError::Service(CreateServiceSpecificCredentialError::LimitExceeded(msg)) => {
+pub async fn create_service_specific_credential(user_name: &str, service_name: &str) -> IamResult<Credential> {
+    let cred = time_api_call("CreateServiceSpecificCredential", IAM_CLIENT.create_service_specific_credential()
+        .user_name(user_name)
+        .service_name(service_name)
+        .send())
+        .await
+        .map_err(|err| match err.as_service_error() {
+            Some(e) if e.is_limit_exceeded_exception() => {
                 IamError::LimitExceeded {
-                    message: msg
+                    message: message(e)
                 }
             },
-            RusotoError::Service(CreateServiceSpecificCredentialError::NoSuchEntity(msg)) => {
+            Some(e) if e.is_no_such_entity_exception() => {
                 IamError::EntityNotFound {
-                    message: msg
+                    message: message(e)
                 }
             },
-            RusotoError::Service(CreateServiceSpecificCredentialError::ServiceNotSupported(msg)) => {
+            Some(e) if e.is_service_not_supported_exception() => {
                 IamError::ServiceNotSupported {
-                    message: msg
+                    message: message(e)
                 }
             },
-            err => IamError::RusotoError {
-                message: format!("{:?}", err)
-            }
+            _ => sdk_error("CreateServiceSpecificCredential", err)
         })?;
-    
-    cred.service_specific_credential
-        .ok_or_else(|| IamError::MissingCredentialInResponse)
-        .map(|cred| Credential::from(cred))
-}
-
-pub fn reset_service_specific_credential(id: &str, user_name: Option<&str>, timeout: Duration) -> IamResult<Credential> {
-    let cred = IAM_CLIENT.reset_service_specific_credential(ResetServiceSpecificCredentialRequest {
-        service_specific_credential_id: id.to_string(),
-        user_name: user_name.map(|s| s.to_string()),
-    })
-        .with_timeout(timeout)
-        .sync()
-        .map_err(|err| match err {
-            RusotoError::Service(ResetServiceSpecificCredentialError::NoSuchEntity(msg)) => {
+
+    cred.service_specific_credential()
+        .ok_or(IamError::MissingCredentialInResponse)
+        .map(Credential::from)
+}
+
+pub async fn reset_service_specific_credential(id: &str, user_name: Option<&str>) -> IamResult<Credential> {
+    let cred = time_api_call("ResetServiceSpecificCredential", IAM_CLIENT.reset_service_specific_credential()
+        .service_specific_credential_id(id)
+        .set_user_name(user_name.map(|s| s.to_string()))
+        .send())
+        .await
+        .map_err(|err| match err.as_service_error() {
+            Some(e) if e.is_no_such_entity_exception() => {
                 IamError::EntityNotFound {
-                    message: msg
+                    message: message(e)
                 }
             },
-            err => IamError::RusotoError {
-                message: format!("{:?}", err)
-            }
+            _ => sdk_error("ResetServiceSpecificCredential", err)
         })?;
-    
-    cred.service_specific_credential
-        .ok_or_else(|| IamError::MissingCredentialInResponse)
-        .map(|cred| Credential::from(cred))
-}
-
-pub fn update_service_specific_credential(id: &str, user_name: Option<&str>, status: CredentialStatus, timeout: Duration) -> IamResult<()> {
-    IAM_CLIENT.update_service_specific_credential(UpdateServiceSpecificCredentialRequest {
-        service_specific_credential_id: id.to_string(),
-        user_name: user_name.map(|s| s.to_string()),
-        status: status.into(),
-    })
-        .with_timeout(timeout)
-        .sync()
-        .map_err(|err| match err {
-            RusotoError::Service(UpdateServiceSpecificCredentialError::NoSuchEntity(msg)) => {
+
+    cred.service_specific_credential()
+        .ok_or(IamError::MissingCredentialInResponse)
+        .map(Credential::from)
+}
+
+pub async fn update_service_specific_credential(id: &str, user_name: Option<&str>, status: CredentialStatus) -> IamResult<()> {
+    let status: String = status.into();
+
+    time_api_call("UpdateServiceSpecificCredential", IAM_CLIENT.update_service_specific_credential()
+        .service_specific_credential_id(id)
+        .set_user_name(user_name.map(|s| s.to_string()))
+        .status(StatusType::from(status.as_str()))
+        .send())
+        .await
+        .map_err(|err| match err.as_service_error() {
+            Some(e) if e.is_no_such_entity_exception() => {
                 IamError::EntityNotFound {
-                    message: msg
+                    message: message(e)
                 }
             },
-            err => IamError::RusotoError {
-                message: format!("{:?}", err)
-            }
+            _ => sdk_error("UpdateServiceSpecificCredential", err)
         })
-}
\ No newline at end of file
+        .map(|_| ())
+}
+
+pub async fn get_user_path(user_name: &str) -> IamResult<String> {
+    info!("getting user user_name={}", user_name);
+
+    time_api_call("GetUser", IAM_CLIENT.get_user()
+        .user_name(user_name)
+        .send())
+        .await
+        .map_err(|err| match err.as_service_error() {
+            Some(e) if e.is_no_such_entity_exception() => {
+                IamError::EntityNotFound {
+                    message: message(e)
+                }
+            },
+            _ => sdk_error("GetUser", err)
+        })
+        .map(|response| response.user().map(|user| user.path().to_string()).unwrap_or_default())
+}
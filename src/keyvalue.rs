@@ -0,0 +1,284 @@
+use std::error::Error as StdError;
+use std::fmt;
+use crate::sensitive::Sensitive;
+
+/// One line of a key-value document.
+#[derive(Clone, PartialEq)]
+enum Line {
+    /// A blank line, comment or `[section]` header, written back as it was read.
+    Verbatim(Sensitive<String>),
+    /// A `KEY=value` line. `raw` is written back while the value is unchanged.
+    Entry {
+        raw: Sensitive<String>,
+        prefix: String,
+        key: String,
+        separator: String,
+        value: Sensitive<String>,
+    },
+}
+
+/// A dotenv or ini style secret value: `KEY=value` lines, with `#` and `;` comments,
+/// an optional `export ` prefix, `[section]` headers and single or double quoted values.
+///
+/// Comments, ordering, quoting and spacing are kept, so a document that is read and written
+/// without changes is identical, and a changed value only rewrites its own line.
+/// Sections are only kept for writing back; keys are looked up regardless of section.
+#[derive(Clone, PartialEq)]
+pub struct KeyValueDocument {
+    lines: Vec<Line>,
+    trailing_newline: bool,
+}
+
+/// Prints the keys only, since comments and values may hold credentials.
+impl fmt::Debug for KeyValueDocument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.entries().map(|(key, _)| key)).finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyValueParseError(String);
+
+impl StdError for KeyValueParseError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for KeyValueParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "key value parse error: {}", self.0)
+    }
+}
+
+/// An empty document, whose lines will each end with a newline.
+impl Default for KeyValueDocument {
+    fn default() -> Self {
+        KeyValueDocument {
+            lines: vec![],
+            trailing_newline: true,
+        }
+    }
+}
+
+impl KeyValueDocument {
+    /// Parses a document. Errors give the line number but never the line, which may hold a credential.
+    pub fn parse(text: &str) -> Result<Self, KeyValueParseError> {
+        let trailing_newline = text.ends_with('\n');
+        let body = if trailing_newline { &text[..text.len() - 1] } else { text };
+
+        let mut lines = vec![];
+
+        if !text.is_empty() {
+            for (index, line) in body.split('\n').enumerate() {
+                lines.push(parse_line(line).map_err(|message| KeyValueParseError(format!("line {} {}", index + 1, message)))?);
+            }
+        }
+
+        Ok(KeyValueDocument {
+            lines,
+            trailing_newline,
+        })
+    }
+
+    /// Every key and its value, in document order.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry { key, value, .. } => Some((&key[..], &value.expose()[..])),
+            Line::Verbatim(_) => None,
+        })
+    }
+
+    /// Changes the value of the last line with the key, which is the one dotenv loaders read, keeping its prefix and separator,
+    /// or appends a new line if the key isn't in the document.
+    pub fn set(&mut self, key: &str, new_value: &str) {
+        let existing = self.lines.iter_mut().rev().find_map(|line| match line {
+            Line::Entry { key: k, .. } if k == key => Some(line),
+            _ => None,
+        });
+
+        match existing {
+            Some(Line::Entry { raw, prefix, key, separator, value }) => {
+                if value.expose() != new_value {
+                    *raw = Sensitive::new(format!("{}{}{}{}", prefix, key, separator, quote(new_value)));
+                    *value = Sensitive::new(new_value.to_string());
+                }
+            },
+            _ => self.lines.push(Line::Entry {
+                raw: Sensitive::new(format!("{}={}", key, quote(new_value))),
+                prefix: String::new(),
+                key: key.to_string(),
+                separator: "=".to_string(),
+                value: Sensitive::new(new_value.to_string()),
+            }),
+        }
+    }
+
+    /// Removes every line with the key.
+    pub fn remove(&mut self, key: &str) {
+        self.lines.retain(|line| !matches!(line, Line::Entry { key: k, .. } if k == key));
+    }
+
+    pub fn render(&self) -> Sensitive<String> {
+        let mut text = self.lines.iter()
+            .map(|line| match line {
+                Line::Verbatim(raw) | Line::Entry { raw, .. } => &raw.expose()[..],
+            })
+            .collect::<Vec<&str>>()
+            .join("\n");
+
+        if self.trailing_newline && !self.lines.is_empty() {
+            text.push('\n');
+        }
+
+        Sensitive::new(text)
+    }
+}
+
+fn parse_line(line: &str) -> Result<Line, &'static str> {
+    let trimmed = line.trim();
+
+    if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') || (trimmed.starts_with('[') && trimmed.ends_with(']')) {
+        return Ok(Line::Verbatim(Sensitive::new(line.to_string())));
+    }
+
+    let (before, after) = line.split_once('=').ok_or("is not a comment, section or KEY=value")?;
+
+    let indent = &before[..before.len() - before.trim_start().len()];
+    let mut name = before.trim();
+    let mut prefix = indent.to_string();
+    if let Some(rest) = name.strip_prefix("export ") {
+        prefix.push_str(&name[..name.len() - rest.trim_start().len()]);
+        name = rest.trim_start();
+    }
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err("has an invalid key");
+    }
+
+    let separator = format!(
+        "{}={}",
+        &before[before.trim_end().len()..],
+        &after[..after.len() - after.trim_start().len()],
+    );
+
+    Ok(Line::Entry {
+        raw: Sensitive::new(line.to_string()),
+        prefix,
+        key: name.to_string(),
+        separator,
+        value: Sensitive::new(unquote(after.trim())?),
+    })
+}
+
+fn unquote(value: &str) -> Result<String, &'static str> {
+    if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        return Ok(value[1..value.len() - 1].to_string());
+    }
+
+    if !value.starts_with('"') {
+        return Ok(value.to_string());
+    }
+
+    if value.len() < 2 || !value.ends_with('"') {
+        return Err("has an unterminated quoted value");
+    }
+
+    let mut unquoted = String::new();
+    let mut chars = value[1..value.len() - 1].chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unquoted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unquoted.push('\n'),
+            Some('r') => unquoted.push('\r'),
+            Some('t') => unquoted.push('\t'),
+            Some(c) => unquoted.push(c),
+            None => return Err("has an unterminated quoted value"),
+        }
+    }
+
+    Ok(unquoted)
+}
+
+/// Writes the value bare if it reads back the same, otherwise double quoted.
+fn quote(value: &str) -> String {
+    let bare = !value.is_empty()
+        && value.trim() == value
+        && !value.starts_with(['"', '\''])
+        && !value.contains(['\n', '\r']);
+
+    if bare {
+        return value.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = "# database credentials\n[default]\nexport USERNAME = bob\nPASSWORD='p#ss word'\nURL=\"https://example.com/?a=b\"\n\n; trailing comment\n";
+
+    #[test]
+    pub fn test_key_value_round_trip() {
+        let document = KeyValueDocument::parse(DOCUMENT).expect("parse error");
+
+        assert_eq!(document.entries().collect::<Vec<_>>(), vec![
+            ("USERNAME", "bob"),
+            ("PASSWORD", "p#ss word"),
+            ("URL", "https://example.com/?a=b"),
+        ]);
+        assert_eq!(document.render().expose(), DOCUMENT);
+        assert_eq!(format!("{:?}", document), r#"["USERNAME", "PASSWORD", "URL"]"#);
+
+        let document = KeyValueDocument::parse("A=1").expect("parse error");
+        assert_eq!(document.render().expose(), "A=1");
+    }
+
+    #[test]
+    pub fn test_key_value_set_and_remove() {
+        let mut document = KeyValueDocument::parse(DOCUMENT).expect("parse error");
+
+        document.set("USERNAME", "alice");
+        document.set("PASSWORD", " \"new\"\\pw");
+        document.set("TOKEN", "t=1");
+        document.remove("URL");
+
+        let text = document.render();
+        assert_eq!(text.expose(), "# database credentials\n[default]\nexport USERNAME = alice\nPASSWORD=\" \\\"new\\\"\\\\pw\"\n\n; trailing comment\nTOKEN=t=1\n");
+
+        let reread = KeyValueDocument::parse(text.expose()).expect("parse error");
+        assert_eq!(reread.entries().collect::<Vec<_>>(), vec![
+            ("USERNAME", "alice"),
+            ("PASSWORD", " \"new\"\\pw"),
+            ("TOKEN", "t=1"),
+        ]);
+    }
+
+    #[test]
+    pub fn test_key_value_parse_errors() {
+        assert_eq!(
+            KeyValueDocument::parse("A=1\nhunter2\n"),
+            Err(KeyValueParseError("line 2 is not a comment, section or KEY=value".to_string())),
+        );
+        assert!(KeyValueDocument::parse("A=\"unterminated").is_err());
+        assert!(KeyValueDocument::parse("=1").is_err());
+    }
+}
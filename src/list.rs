@@ -0,0 +1,31 @@
+use aws_sdk_secretsmanager::types::{Filter, FilterNameStringType, SecretListEntry};
+use crate::error::{RotatorResult, RotatorError};
+use crate::SM_CLIENT;
+use crate::metrics::time_api_call;
+
+/// Lists every secret with the given tag key, following pagination.
+pub async fn list_secrets_with_tag(tag_key: &str) -> RotatorResult<Vec<SecretListEntry>> {
+    info!("listing secrets tagged {}", tag_key);
+
+    let mut secrets = vec![];
+    let mut next_token = None;
+
+    loop {
+        let response = time_api_call("ListSecrets", SM_CLIENT.list_secrets()
+            .filters(Filter::builder()
+                .key(FilterNameStringType::TagKey)
+                .values(tag_key)
+                .build())
+            .set_next_token(next_token)
+            .send())
+            .await
+            .map_err(|e| RotatorError::from_sdk("ListSecrets", &e).unwrap_or_else(|| RotatorError::ListSecrets(format!("{:?}", e))))?;
+
+        secrets.extend(response.secret_list.unwrap_or_default());
+
+        next_token = response.next_token;
+        if next_token.is_none() {
+            return Ok(secrets);
+        }
+    }
+}
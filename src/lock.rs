@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::operation::delete_item::DeleteItemError;
+use aws_sdk_dynamodb::operation::put_item::PutItemError;
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValuesOnConditionCheckFailure};
+use aws_sdk_secretsmanager::types::Tag;
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use crate::{DYNAMODB_CLIENT, SM_CLIENT, RotatorError, RotatorResult};
+use crate::describe::describe_secret;
+use crate::metrics::time_api_call;
+use uuid::Uuid;
+
+const TAGS_ENVIRONMENT_VARIABLE: &str = "ROTATOR_LEASE_TAGS";
+const TABLE_ENVIRONMENT_VARIABLE: &str = "ROTATOR_LEASE_TABLE";
+
+pub const LEASE_TAG: &str = "rotator:lease";
+
+lazy_static! {
+    static ref MEMORY_LEASES: Arc<Mutex<HashMap<String, Lease>>> = Arc::new(Mutex::new(HashMap::new()));
+}
+
+/// The right for one invocation to run rotation steps on a secret, until it expires.
+///
+/// `holder` identifies the invocation; the client request token is only recorded for whoever finds the lease held.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lease {
+    pub secret_id: String,
+    pub holder: String,
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Lease {
+    pub fn new(secret_id: &str, holder: &str, token: &str, expires_at: DateTime<Utc>) -> Self {
+        Lease {
+            secret_id: secret_id.to_string(),
+            holder: holder.to_string(),
+            token: token.to_string(),
+            expires_at,
+        }
+    }
+
+    /// A lease keyed on the secret's ARN, so that steps naming the secret by name and by ARN share it.
+    pub async fn for_secret(secret_id: &str, holder: &str, token: &str, expires_at: DateTime<Utc>) -> RotatorResult<Self> {
+        let arn = describe_secret(secret_id).await?.arn;
+
+        Ok(Lease::new(arn.as_deref().unwrap_or(secret_id), holder, token, expires_at))
+    }
+
+    /// A holder id for an invocation that has no request id of its own, such as the CLI.
+    pub fn new_holder() -> String {
+        Uuid::new_v4().to_string()
+    }
+
+    /// Fails if another invocation holds an unexpired lease, even one for the same client request token.
+    pub fn check_holder(&self, holder: Option<&Lease>, now: DateTime<Utc>) -> RotatorResult<()> {
+        match holder {
+            Some(holder) if holder.holder != self.holder && holder.expires_at > now => Err(RotatorError::LeaseHeld {
+                secret_id: self.secret_id.to_string(),
+                holder: Some(holder.holder.to_string()),
+                expires_at: Some(timestamp(holder.expires_at)),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The lease as a tag value, `<holder> <expiry> <token>`.
+    pub fn tag_value(&self) -> String {
+        format!("{} {} {}", self.holder, timestamp(self.expires_at), self.token)
+    }
+
+    pub fn from_tag_value(secret_id: &str, value: &str) -> Option<Self> {
+        let mut parts = value.splitn(3, ' ');
+        let holder = parts.next()?;
+        let expires_at = DateTime::parse_from_rfc3339(parts.next()?).ok()?.with_timezone(&Utc);
+
+        Some(Lease::new(secret_id, holder, parts.next()?, expires_at))
+    }
+}
+
+fn timestamp(t: DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[async_trait]
+pub trait LeaseStore: Send + Sync {
+    /// Takes the lease, or fails with `LeaseHeld` if another invocation holds it.
+    async fn acquire(&self, lease: &Lease) -> RotatorResult<()>;
+
+    /// Gives the lease up, if it's still held.
+    async fn release(&self, lease: &Lease) -> RotatorResult<()>;
+}
+
+/// Holds leases in the `rotator:lease` tag on the secret.
+///
+/// Reading and writing the tag isn't atomic, so two invocations starting at the same moment may both get the lease.
+pub struct TagLeaseStore {
+}
+
+impl TagLeaseStore {
+    async fn holder(&self, secret_id: &str) -> RotatorResult<Option<Lease>> {
+        let secret = describe_secret(secret_id).await?;
+
+        Ok(secret.tags.unwrap_or_default().iter()
+            .find(|tag| tag.key() == Some(LEASE_TAG))
+            .and_then(|tag| tag.value())
+            .and_then(|value| Lease::from_tag_value(secret_id, value)))
+    }
+}
+
+#[async_trait]
+impl LeaseStore for TagLeaseStore {
+    async fn acquire(&self, lease: &Lease) -> RotatorResult<()> {
+        lease.check_holder(self.holder(&lease.secret_id).await?.as_ref(), Utc::now())?;
+
+        time_api_call("TagResource", SM_CLIENT.tag_resource()
+            .secret_id(&lease.secret_id)
+            .tags(Tag::builder()
+                .key(LEASE_TAG)
+                .value(lease.tag_value())
+                .build())
+            .send())
+            .await
+            .map_err(|e| RotatorError::from_sdk("TagResource", &e).unwrap_or_else(|| RotatorError::Lease(format!("{:?}", e))))
+            .map(|_| ())
+    }
+
+    async fn release(&self, lease: &Lease) -> RotatorResult<()> {
+        match self.holder(&lease.secret_id).await? {
+            Some(ref holder) if holder.holder == lease.holder => {},
+            holder => {
+                warn!("lease for secret {} was lost before release, now held by {:?}", lease.secret_id, holder);
+                return Ok(());
+            },
+        }
+
+        time_api_call("UntagResource", SM_CLIENT.untag_resource()
+            .secret_id(&lease.secret_id)
+            .tag_keys(LEASE_TAG)
+            .send())
+            .await
+            .map_err(|e| RotatorError::from_sdk("UntagResource", &e).unwrap_or_else(|| RotatorError::Lease(format!("{:?}", e))))
+            .map(|_| ())
+    }
+}
+
+/// Holds leases in a DynamoDB table keyed on `secretId`, using conditional writes on `holder`.
+///
+/// `expiresAt` is in epoch seconds, so it can be the table's TTL attribute.
+pub struct DynamoDbLeaseStore {
+    table_name: String,
+}
+
+fn lease_from_item(secret_id: &str, item: &HashMap<String, AttributeValue>) -> Option<Lease> {
+    let holder = item.get("holder")?.as_s().ok()?;
+    let token = item.get("token").and_then(|token| token.as_s().ok()).map(String::as_str).unwrap_or_default();
+    let expires_at = item.get("expiresAt")?.as_n().ok()?.parse::<i64>().ok()?;
+
+    Some(Lease::new(secret_id, holder, token, Utc.timestamp_opt(expires_at, 0).single()?))
+}
+
+#[async_trait]
+impl LeaseStore for DynamoDbLeaseStore {
+    async fn acquire(&self, lease: &Lease) -> RotatorResult<()> {
+        time_api_call("PutItem", DYNAMODB_CLIENT.put_item()
+            .table_name(&self.table_name)
+            .item("secretId", AttributeValue::S(lease.secret_id.to_string()))
+            .item("holder", AttributeValue::S(lease.holder.to_string()))
+            .item("token", AttributeValue::S(lease.token.to_string()))
+            .item("expiresAt", AttributeValue::N(lease.expires_at.timestamp().to_string()))
+            .condition_expression("attribute_not_exists(secretId) OR #holder = :holder OR expiresAt < :now")
+            .expression_attribute_names("#holder", "holder")
+            .expression_attribute_values(":holder", AttributeValue::S(lease.holder.to_string()))
+            .expression_attribute_values(":now", AttributeValue::N(Utc::now().timestamp().to_string()))
+            .return_values_on_condition_check_failure(ReturnValuesOnConditionCheckFailure::AllOld)
+            .send())
+            .await
+            .map_err(|e| match e.as_service_error() {
+                Some(PutItemError::ConditionalCheckFailedException(err)) => {
+                    let holder = err.item().and_then(|item| lease_from_item(&lease.secret_id, item));
+
+                    RotatorError::LeaseHeld {
+                        secret_id: lease.secret_id.to_string(),
+                        holder: holder.as_ref().map(|holder| holder.holder.to_string()),
+                        expires_at: holder.as_ref().map(|holder| timestamp(holder.expires_at)),
+                    }
+                },
+                _ => RotatorError::from_sdk("PutItem", &e).unwrap_or_else(|| RotatorError::Lease(format!("{:?}", e))),
+            })
+            .map(|_| ())
+    }
+
+    async fn release(&self, lease: &Lease) -> RotatorResult<()> {
+        let result = time_api_call("DeleteItem", DYNAMODB_CLIENT.delete_item()
+            .table_name(&self.table_name)
+            .key("secretId", AttributeValue::S(lease.secret_id.to_string()))
+            .condition_expression("#holder = :holder")
+            .expression_attribute_names("#holder", "holder")
+            .expression_attribute_values(":holder", AttributeValue::S(lease.holder.to_string()))
+            .send())
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => match e.as_service_error() {
+                Some(DeleteItemError::ConditionalCheckFailedException(_)) => {
+                    warn!("lease for secret {} was lost before release", lease.secret_id);
+                    Ok(())
+                },
+                _ => Err(RotatorError::from_sdk("DeleteItem", &e).unwrap_or_else(|| RotatorError::Lease(format!("{:?}", e)))),
+            },
+        }
+    }
+}
+
+/// Holds leases in this process, which only protects against overlapping steps in one lambda container or CLI run.
+#[derive(Default)]
+pub struct MemoryLeaseStore {
+    leases: Arc<Mutex<HashMap<String, Lease>>>,
+}
+
+#[async_trait]
+impl LeaseStore for MemoryLeaseStore {
+    async fn acquire(&self, lease: &Lease) -> RotatorResult<()> {
+        let mut leases = self.leases.lock().unwrap_or_else(|err| err.into_inner());
+
+        lease.check_holder(leases.get(&lease.secret_id), Utc::now())?;
+        leases.insert(lease.secret_id.to_string(), lease.clone());
+
+        Ok(())
+    }
+
+    async fn release(&self, lease: &Lease) -> RotatorResult<()> {
+        let mut leases = self.leases.lock().unwrap_or_else(|err| err.into_inner());
+
+        if leases.get(&lease.secret_id).map(|holder| holder.holder == lease.holder).unwrap_or(false) {
+            leases.remove(&lease.secret_id);
+        }
+
+        Ok(())
+    }
+}
+
+/// The lease store configured in the lambda environment.
+pub struct Leases {
+    store: Box<dyn LeaseStore>,
+}
+
+impl Leases {
+    pub fn from_environment() -> Self {
+        let store: Box<dyn LeaseStore> = if let Ok(table_name) = std::env::var(TABLE_ENVIRONMENT_VARIABLE) {
+            Box::new(DynamoDbLeaseStore { table_name })
+        } else if std::env::var(TAGS_ENVIRONMENT_VARIABLE).map(|v| v == "true").unwrap_or(false) {
+            Box::new(TagLeaseStore {})
+        } else {
+            Box::new(MemoryLeaseStore { leases: MEMORY_LEASES.clone() })
+        };
+
+        Leases { store }
+    }
+
+    /// Runs `f` while holding the lease, releasing it afterwards whether or not `f` succeeded.
+    ///
+    /// A failure to release is only logged; the lease expires anyway.
+    pub async fn with_lease<T, F: Future<Output = RotatorResult<T>>>(&self, lease: &Lease, f: F) -> RotatorResult<T> {
+        self.store.acquire(lease).await?;
+
+        let result = f.await;
+
+        if let Err(err) = self.store.release(lease).await {
+            error!("error releasing lease for secret {}: {:?}", lease.secret_id, err);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn leases() -> Leases {
+        Leases { store: Box::new(MemoryLeaseStore::default()) }
+    }
+
+    #[test]
+    pub fn test_check_holder() {
+        let now = Utc.with_ymd_and_hms(2026, 7, 15, 12, 0, 0).unwrap();
+        let lease = Lease::new("secret", "b", "token", now + Duration::minutes(5));
+        let held = |holder| Err(RotatorError::LeaseHeld {
+            secret_id: "secret".to_string(),
+            holder: Some(holder),
+            expires_at: Some("2026-07-15T12:01:00Z".to_string()),
+        });
+
+        assert_eq!(lease.check_holder(None, now), Ok(()));
+        assert_eq!(lease.check_holder(Some(&Lease::new("secret", "b", "token", now + Duration::minutes(1))), now), Ok(()));
+        assert_eq!(lease.check_holder(Some(&Lease::new("secret", "a", "other", now - Duration::minutes(1))), now), Ok(()));
+        assert_eq!(lease.check_holder(Some(&Lease::new("secret", "a", "other", now + Duration::minutes(1))), now), held("a".to_string()));
+        assert_eq!(lease.check_holder(Some(&Lease::new("secret", "a", "token", now + Duration::minutes(1))), now), held("a".to_string()));
+    }
+
+    #[test]
+    pub fn test_tag_value() {
+        let lease = Lease::new("secret", "a", "token with spaces", Utc.with_ymd_and_hms(2026, 7, 15, 12, 0, 0).unwrap());
+
+        assert_eq!(lease.tag_value(), "a 2026-07-15T12:00:00Z token with spaces");
+        assert_eq!(Lease::from_tag_value("secret", &lease.tag_value()), Some(lease));
+        assert_eq!(Lease::from_tag_value("secret", "garbage"), None);
+    }
+
+    #[tokio::test]
+    pub async fn test_memory_lease() {
+        let leases = leases();
+        let expires_at = Utc::now() + Duration::minutes(5);
+        let a = Lease::new("secret", "a", "token", expires_at);
+        let b = Lease::new("secret", "b", "token", expires_at);
+
+        let result = leases.with_lease(&a, async {
+            assert!(matches!(leases.store.acquire(&b).await, Err(RotatorError::LeaseHeld { .. })));
+            assert_eq!(leases.store.release(&b).await, Ok(()));
+            assert!(matches!(leases.store.acquire(&b).await, Err(RotatorError::LeaseHeld { .. })));
+            assert_eq!(leases.store.acquire(&a).await, Ok(()));
+            assert_eq!(leases.store.acquire(&Lease::new("other", "b", "token", expires_at)).await, Ok(()));
+            Ok(1)
+        }).await;
+
+        assert_eq!(result, Ok(1));
+        assert_eq!(leases.with_lease(&b, async { Ok(2) }).await, Ok(2));
+    }
+}
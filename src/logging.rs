@@ -0,0 +1,123 @@
+use std::io::Write;
+use std::sync::Mutex;
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::Level;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use crate::{RotatorEvent, RotatorStep};
+
+const LOG_FORMAT_ENVIRONMENT_VARIABLE: &str = "ROTATOR_LOG_FORMAT";
+
+/// Fields identifying the rotation that is in progress, added to every JSON log line.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct LogContext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step: Option<RotatorStep>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_request_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_type: Option<String>,
+}
+
+lazy_static! {
+    static ref CONTEXT: Mutex<LogContext> = Mutex::new(LogContext::default());
+}
+
+/// Sets up `env_logger`, writing JSON lines if `ROTATOR_LOG_FORMAT=json`.
+pub fn init() {
+    match std::env::var(LOG_FORMAT_ENVIRONMENT_VARIABLE) {
+        Ok(ref format) if format == "json" => {
+            env_logger::Builder::from_default_env()
+                .format(|buf, record| {
+                    let line = format_json(record.level(), record.target(), &record.args().to_string(), &context(), Utc::now());
+                    writeln!(buf, "{}", line)
+                })
+                .init();
+        },
+        _ => env_logger::init(),
+    }
+}
+
+fn context() -> LogContext {
+    CONTEXT.lock().map(|context| context.clone()).unwrap_or_default()
+}
+
+fn update<F: FnOnce(&mut LogContext)>(f: F) {
+    if let Ok(mut context) = CONTEXT.lock() {
+        f(&mut context);
+    }
+}
+
+/// Clears the context left over from the previous invocation.
+pub fn start_invocation(request_id: Option<&str>) {
+    update(|context| {
+        *context = LogContext {
+            request_id: request_id.map(|id| id.to_string()),
+            ..Default::default()
+        };
+    });
+}
+
+pub fn set_event(e: &RotatorEvent) {
+    update(|context| {
+        context.secret_id = Some(e.secret_id.to_string());
+        context.step = Some(e.step);
+        context.client_request_token = Some(e.client_request_token.to_string());
+        context.resource_type = None;
+    });
+}
+
+pub fn set_resource_type(resource_type: &str) {
+    update(|context| context.resource_type = Some(resource_type.to_string()));
+}
+
+pub fn format_json(level: Level, target: &str, message: &str, context: &LogContext, timestamp: DateTime<Utc>) -> String {
+    let mut line = Map::new();
+
+    line.insert("timestamp".to_string(), Value::String(timestamp.to_rfc3339_opts(SecondsFormat::Millis, true)));
+    line.insert("level".to_string(), Value::String(level.to_string()));
+    line.insert("target".to_string(), Value::String(target.to_string()));
+    line.insert("message".to_string(), Value::String(message.to_string()));
+
+    if let Ok(Value::Object(fields)) = serde_json::to_value(context) {
+        line.extend(fields);
+    }
+
+    Value::Object(line).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    pub fn test_format_json() {
+        let context = LogContext {
+            request_id: Some("request".to_string()),
+            secret_id: Some("secret".to_string()),
+            step: Some(RotatorStep::CreateSecret),
+            client_request_token: Some("token".to_string()),
+            resource_type: None,
+        };
+        let timestamp = Utc.with_ymd_and_hms(2026, 7, 15, 12, 0, 0).unwrap();
+
+        let line = format_json(Level::Info, "rotator::create", "createSecret: Successfully put secret", &context, timestamp);
+        let value: Value = serde_json::from_str(&line).expect("json parse error");
+
+        assert_eq!(value, serde_json::json!({
+            "timestamp": "2026-07-15T12:00:00.000Z",
+            "level": "INFO",
+            "target": "rotator::create",
+            "message": "createSecret: Successfully put secret",
+            "request_id": "request",
+            "secret_id": "secret",
+            "step": "createSecret",
+            "client_request_token": "token",
+        }));
+    }
+}
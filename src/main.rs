@@ -3,14 +3,14 @@ use serde::{Serialize, Deserialize};
 extern crate log;
 #[macro_use]
 extern crate lazy_static;
-use lambda_runtime::{Context, lambda};
-use lambda_runtime::error::LambdaResultExt;
-use failure::{Compat, Error};
-use rusoto_core::Region;
-use rusoto_iam::IamClient;
-use rusoto_secretsmanager::SecretsManagerClient;
-use std::time::Duration;
-use std::collections::HashMap;
+use aws_config::{BehaviorVersion, Region, SdkConfig};
+use aws_config::timeout::TimeoutConfig;
+use aws_sdk_secretsmanager::operation::describe_secret::DescribeSecretOutput;
+use lambda_runtime::{LambdaEvent, service_fn};
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime};
+use chrono::Utc;
 
 mod describe;
 mod create;
@@ -23,19 +23,87 @@ mod resource;
 mod error;
 mod config;
 mod iam;
+mod template;
+mod policy;
+mod cli;
+mod window;
+mod logging;
+mod metrics;
+mod notify;
+mod audit;
+mod sensitive;
+mod list;
+mod sweep;
+mod trigger;
+mod drift;
+mod dispatch;
+mod health;
+mod lock;
+mod stage;
+mod rollback;
+mod schema;
+mod keyvalue;
+mod replica;
+#[cfg(test)]
+mod fixtures;
 
 use describe::describe_secret;
-use create::create_secret;
-use set::set_secret;
-use test::test_secret;
-use finish::finish_secret;
-use resource::{ServiceSpecificCredentialResource, Resource};
+use create::{create_secret, plan_create_secret};
+use set::{set_secret, plan_set_secret};
+use test::{test_secret, plan_test_secret};
+use finish::{finish_secret, plan_finish_secret};
+use cli::{Command, parse_args};
+use window::check_window;
+use value::{get_secret_value, ResourceChange, ValueFormat};
+use resource::{Resource, new_resource};
 pub use error::*;
-use config::{Config, ResourceType};
+use config::{Config, value_format};
+use replica::{Replication, check_primary, current_region};
+use policy::{Policy, enforce};
+use notify::{Notification, Notifiers};
+use sweep::{ScheduledEvent, SweepReport, sweep};
+use drift::{CloudTrailEvent, DriftReport, handle_credential_event};
+use dispatch::{AdminCommand, AdminOutput, RotatorInput};
+use health::{HealthReport, health_check};
+use trigger::rotate_secret;
+use lock::{Lease, Leases};
+use stage::{VersionStages, AWSCURRENT, AWSPENDING};
+use rollback::{RollbackReport, ROLLBACK_TOKEN, rollback};
+
+/// How long one attempt at an AWS API call may take.
+const API_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Time kept back from the lambda deadline to report a step that ran out of time.
+const DEADLINE_MARGIN: Duration = Duration::from_millis(500);
+
+/// How long `rotator rotate` holds the secret's lease, since there's no lambda deadline.
+const CLI_LEASE_DURATION: Duration = Duration::from_secs(15 * 60);
+
+static SDK_CONFIG: OnceLock<SdkConfig> = OnceLock::new();
 
 lazy_static! {
-    pub static ref SM_CLIENT: SecretsManagerClient = SecretsManagerClient::new(Region::default());
-    pub static ref IAM_CLIENT: IamClient = IamClient::new(Region::UsEast1);
+    pub static ref SM_CLIENT: aws_sdk_secretsmanager::Client = aws_sdk_secretsmanager::Client::new(sdk_config());
+    pub static ref IAM_CLIENT: aws_sdk_iam::Client = aws_sdk_iam::Client::from_conf(aws_sdk_iam::config::Builder::from(sdk_config())
+        .region(Region::from_static("us-east-1"))
+        .build());
+    pub static ref SNS_CLIENT: aws_sdk_sns::Client = aws_sdk_sns::Client::new(sdk_config());
+    pub static ref DYNAMODB_CLIENT: aws_sdk_dynamodb::Client = aws_sdk_dynamodb::Client::new(sdk_config());
+}
+
+/// Loads region and credentials from the environment. Must be called before any client is used.
+async fn load_sdk_config() {
+    let config = aws_config::defaults(BehaviorVersion::latest())
+        .timeout_config(TimeoutConfig::builder()
+            .operation_attempt_timeout(API_TIMEOUT)
+            .build())
+        .load()
+        .await;
+
+    SDK_CONFIG.set(config).ok();
+}
+
+fn sdk_config() -> &'static SdkConfig {
+    SDK_CONFIG.get().expect("aws config used before it was loaded")
 }
 
 #[derive(Clone, Debug, PartialEq, Deserialize)]
@@ -48,9 +116,25 @@ pub struct RotatorEvent {
 
     #[serde(rename="Step")]
     step: RotatorStep,
+
+    /// Describe what the step would do instead of doing it.
+    #[serde(rename="DryRun", default)]
+    dry_run: bool,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum RotatorResponse {
+    Rotation(RotatorOutput),
+    Sweep(SweepReport),
+    Drift(DriftReport),
+    Plan(Vec<RotatorOutput>),
+    Admin(AdminOutput),
+    Health(HealthReport),
+    Rollback(RollbackReport),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum RotatorStep {
     #[serde(rename="createSecret")]
     CreateSecret,
@@ -65,75 +149,410 @@ pub enum RotatorStep {
     FinishSecret,
 }
 
+impl RotatorStep {
+    pub const ALL: [RotatorStep; 4] = [
+        RotatorStep::CreateSecret,
+        RotatorStep::SetSecret,
+        RotatorStep::TestSecret,
+        RotatorStep::FinishSecret,
+    ];
+
+    /// The step name as it appears in rotation events.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RotatorStep::CreateSecret => "createSecret",
+            RotatorStep::SetSecret => "setSecret",
+            RotatorStep::TestSecret => "testSecret",
+            RotatorStep::FinishSecret => "finishSecret",
+        }
+    }
+}
+
+/// What a rotation step did.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StepOutcome {
+    /// Nothing needed doing, e.g. the version was already AWSCURRENT or already had a pending secret.
+    pub no_op: bool,
+    /// Changes made to the rotated resource.
+    pub changes: Vec<ResourceChange>,
+    /// Secret version ids the step read or changed.
+    pub versions: Vec<String>,
+}
+
+impl StepOutcome {
+    pub fn no_op(versions: Vec<String>) -> Self {
+        StepOutcome {
+            no_op: true,
+            versions,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct RotatorOutput {
     message: String,
+
+    step: RotatorStep,
+
+    no_op: bool,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    changes: Vec<ResourceChange>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    versions: Vec<String>,
+
+    duration_ms: f64,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    plan: Vec<String>,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    env_logger::init();
-    lambda!(lambda_handler);
+impl RotatorOutput {
+    fn new(message: &str, step: RotatorStep, outcome: StepOutcome) -> Self {
+        RotatorOutput {
+            message: message.to_string(),
+            step,
+            no_op: outcome.no_op,
+            changes: outcome.changes,
+            versions: outcome.versions,
+            duration_ms: 0.0,
+            plan: vec![],
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), lambda_runtime::Error> {
+    logging::init();
+    load_sdk_config().await;
+
+    match parse_args(std::env::args().skip(1))? {
+        Command::Lambda => lambda_runtime::run(service_fn(lambda_handler)).await?,
+        Command::Rotate(e) => {
+            let lease = Lease::for_secret(&e.secret_id, &Lease::new_holder(), &e.client_request_token, (SystemTime::now() + CLI_LEASE_DURATION).into()).await?;
+            let output = Leases::from_environment().with_lease(&lease, rotate(e)).await?;
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        },
+        Command::Plan { secret_id, client_request_token } => {
+            for output in plan_all(&secret_id, &client_request_token).await {
+                for line in output.plan.iter() {
+                    println!("{:?}: {}", output.step, line);
+                }
+            }
+        },
+        Command::Sweep => {
+            let report = sweep(Utc::now()).await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        },
+        Command::Rollback { secret_id } => {
+            let report = rollback_with_lease(&secret_id, &Lease::new_holder(), SystemTime::now() + CLI_LEASE_DURATION).await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        },
+    }
 
     Ok(())
 }
 
-fn lambda_handler(e: RotatorEvent, _c: Context) -> Result<RotatorOutput, Compat<Error>> {
+/// Describes what every rotation step would do, in order.
+///
+/// A step that fails is reported in its plan, so the later steps are still described.
+async fn plan_all(secret_id: &str, client_request_token: &str) -> Vec<RotatorOutput> {
+    let mut outputs = vec![];
+
+    for step in RotatorStep::ALL.iter() {
+        let result = rotate(RotatorEvent {
+            secret_id: secret_id.to_string(),
+            client_request_token: client_request_token.to_string(),
+            step: *step,
+            dry_run: true,
+        }).await;
+
+        outputs.push(result.unwrap_or_else(|err| RotatorOutput {
+            plan: vec![format!("would fail with {}: {}", err.code(), err.message())],
+            ..RotatorOutput::new("dry run failed", *step, StepOutcome::default())
+        }));
+    }
+
+    outputs
+}
+
+async fn lambda_handler(event: LambdaEvent<serde_json::Value>) -> Result<RotatorResponse, RotatorError> {
+    let (event, c) = event.into_parts();
+
+    logging::start_invocation(Some(&c.request_id));
+    metrics::start_invocation();
+
+    let input = RotatorInput::parse(event).inspect_err(|err| {
+        error!("invalid event code={} error={}", err.code(), err.message());
+    })?;
+
+    match input {
+        RotatorInput::Rotation(e) => handle_rotation(e, &c.request_id, c.deadline()).await.map(RotatorResponse::Rotation),
+        RotatorInput::CloudTrail(e) => handle_cloudtrail(e).await.map(RotatorResponse::Drift),
+        RotatorInput::Scheduled(e) => handle_scheduled(e).await.map(RotatorResponse::Sweep),
+        RotatorInput::Admin(command) => handle_admin(command, &c.request_id, c.deadline()).await,
+        RotatorInput::HealthCheck => Ok(RotatorResponse::Health(health_check())),
+    }
+}
+
+async fn handle_admin(command: AdminCommand, request_id: &str, deadline: SystemTime) -> RotatorResult<RotatorResponse> {
+    info!("admin command {:?}", command);
+
+    let response = match command {
+        AdminCommand::Sweep => sweep(Utc::now()).await.map(RotatorResponse::Sweep),
+        AdminCommand::Plan { secret_id, client_request_token } => Ok(RotatorResponse::Plan(plan_all(&secret_id, &client_request_token).await)),
+        AdminCommand::Rotate { secret_id } => rotate_secret(&secret_id).await.map(|_| RotatorResponse::Admin(AdminOutput {
+            message: "rotation started".to_string(),
+            secret_id,
+        })),
+        AdminCommand::Rollback { secret_id } => rollback_with_lease(&secret_id, request_id, deadline).await.map(RotatorResponse::Rollback),
+    };
+
+    if let Err(ref err) = response {
+        error!("admin command failed code={} class={:?} error={:?}", err.code(), err.class(), err);
+    }
+
+    response
+}
+
+async fn handle_cloudtrail(e: CloudTrailEvent) -> RotatorResult<DriftReport> {
+    info!("{} event {}", e.detail_type, e.detail.event_name);
+
+    let report = handle_credential_event(&e).await;
+
+    if let Err(ref err) = report {
+        error!("credential event failed code={} class={:?} error={:?}", err.code(), err.class(), err);
+    }
+
+    report
+}
+
+async fn handle_scheduled(e: ScheduledEvent) -> RotatorResult<SweepReport> {
+    info!("sweep started by {} {} at {:?}", e.source, e.detail_type, e.time);
+
+    let report = sweep(Utc::now()).await;
+
+    if let Err(ref err) = report {
+        error!("sweep failed code={} class={:?} error={:?}", err.code(), err.class(), err);
+    }
+
+    report
+}
+
+async fn handle_rotation(e: RotatorEvent, request_id: &str, deadline: SystemTime) -> RotatorResult<RotatorOutput> {
     info!("rotator input event={:?}", e);
 
-    rotate(e).failure_compat()?;
-    
-    let output = RotatorOutput {
-        message: format!("ok"),
+    let (secret_id, step, dry_run) = (e.secret_id.to_string(), e.step, e.dry_run);
+    let start = Instant::now();
+    let result = if dry_run {
+        with_deadline(&secret_id, step, deadline, rotate(e)).await
+    } else {
+        // held until the lambda deadline if the step is killed without releasing it
+        match Lease::for_secret(&secret_id, request_id, &e.client_request_token, deadline.into()).await {
+            Ok(lease) => Leases::from_environment().with_lease(&lease, with_deadline(&secret_id, step, deadline, rotate(e))).await,
+            Err(err) => Err(err),
+        }
     };
 
+    if !dry_run {
+        if let Err(err) = metrics::emit(&mut std::io::stdout(), step, start.elapsed(), result.as_ref().err()) {
+            error!("error writing metrics: {:?}", err);
+        }
+
+        // a declined rotation is expected, so it isn't reported as a failure
+        if !matches!(result, Err(RotatorError::RotationDeclined { .. })) {
+            Notifiers::from_environment().notify(&Notification::new(&secret_id, step, result.as_ref().err())).await;
+        }
+    }
+
+    match result {
+        Err(RotatorError::RotationDeclined { ref reason, .. }) => info!("rotation declined secret_id={} step={:?}: {}", secret_id, step, reason),
+        Err(ref err) => error!("rotation failed code={} class={:?} error={:?}", err.code(), err.class(), err),
+        Ok(_) => {},
+    }
+
+    let output = result?;
+
     info!("rotator output={:?}", output);
 
     Ok(output)
 }
 
-fn rotate(e: RotatorEvent) -> RotatorResult<()> {
-    let timeout = Duration::from_secs(2);
+/// Rolls back while holding the secret's lease, so that no rotation step runs at the same time.
+async fn rollback_with_lease(secret_id: &str, holder: &str, expires_at: SystemTime) -> RotatorResult<RollbackReport> {
+    let lease = Lease::for_secret(secret_id, holder, ROLLBACK_TOKEN, expires_at.into()).await?;
 
-    let secret = describe_secret(&e.secret_id, timeout)?;
-    
-    let config = Config::new_for_secret(&e.secret_id, secret.tags)?;
-    info!("rotating secret event={:?} config={:?}", e, config);
+    Leases::from_environment().with_lease(&lease, rollback(secret_id)).await
+}
 
-    let resource = match config.resource_type {
-        ResourceType::ServiceSpecificCredential => Box::new(ServiceSpecificCredentialResource::new(&config)?) as Box<dyn Resource>,
-    };
+/// Runs a step until shortly before the lambda deadline, leaving time to report the failure.
+///
+/// The step's future is dropped at the deadline, cancelling any AWS call in flight.
+async fn with_deadline<F: Future<Output = RotatorResult<RotatorOutput>>>(secret_id: &str, step: RotatorStep, deadline: SystemTime, f: F) -> RotatorResult<RotatorOutput> {
+    let remaining = deadline.duration_since(SystemTime::now())
+        .unwrap_or_default()
+        .saturating_sub(DEADLINE_MARGIN);
 
+    tokio::time::timeout(remaining, f)
+        .await
+        .unwrap_or_else(|_| {
+            error!("deadline exceeded secret_id={} step={:?}", secret_id, step);
+            Err(RotatorError::DeadlineExceeded {
+                secret_id: secret_id.to_string(),
+                step: step.name().to_string(),
+            })
+        })
+}
+
+/// The outcome of checking the client request token against the secret's version stages.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum VersionCheck {
+    AlreadyCurrent,
+    Pending,
+}
+
+fn check_version_stages(e: &RotatorEvent, secret: &DescribeSecretOutput) -> RotatorResult<VersionCheck> {
     if !secret.rotation_enabled.unwrap_or(false) {
         error!("Secret {} is not enabled for rotation", e.secret_id);
-        return Err(RotatorError::RotationNotEnabled { secret_id: e.secret_id });
+        return Err(RotatorError::RotationNotEnabled { secret_id: e.secret_id.to_string() });
     }
 
-    let version_ids_to_stages = secret.version_ids_to_stages.unwrap_or(HashMap::new());
-    info!("found versions {:?}", version_ids_to_stages);
+    let stages = VersionStages::from_secret(secret);
+    info!("found versions {:?}", stages);
 
-    let version = version_ids_to_stages.get(&e.client_request_token);
-    if version.is_none() {
+    if stages.labels(&e.client_request_token).is_none() {
         error!("Secret version {} has no stage for rotation of secret {}.", e.client_request_token, e.secret_id);
-        return Err(RotatorError::NoStageForRotation { secret_id: e.secret_id, version: e.client_request_token });
+        return Err(RotatorError::NoStageForRotation { secret_id: e.secret_id.to_string(), version: e.client_request_token.to_string() });
     }
-    let version = version.unwrap();
-    if version.contains(&"AWSCURRENT".to_string()) {
+    if stages.has_label(&e.client_request_token, AWSCURRENT) {
         info!("Secret version {} already set as AWSCURRENT for secret {}.", e.client_request_token, e.secret_id);
-        return Ok(());
+        return Ok(VersionCheck::AlreadyCurrent);
     }
-    if !version.contains(&"AWSPENDING".to_string()) {
+    if !stages.has_label(&e.client_request_token, AWSPENDING) {
         error!("Secret version {} not set as AWSPENDING for rotation of secret {}.", e.client_request_token, e.secret_id);
-        return Err(RotatorError::NotSetAsPending { secret_id: e.secret_id, version: e.client_request_token });
+        return Err(RotatorError::NotSetAsPending { secret_id: e.secret_id.to_string(), version: e.client_request_token.to_string() });
     }
 
-    match e.step {
-        RotatorStep::CreateSecret => create_secret(e, resource)?,
-        RotatorStep::SetSecret => set_secret(e)?,
-        RotatorStep::TestSecret => test_secret(e)?,
-        RotatorStep::FinishSecret => finish_secret(e)?,
+    Ok(VersionCheck::Pending)
+}
+
+async fn rotate(e: RotatorEvent) -> RotatorResult<RotatorOutput> {
+    let start = Instant::now();
+
+    let mut output = run_step(e).await?;
+    output.duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    Ok(output)
+}
+
+async fn run_step(e: RotatorEvent) -> RotatorResult<RotatorOutput> {
+    logging::set_event(&e);
+
+    let secret = describe_secret(&e.secret_id).await?;
+    check_primary(&e.secret_id, &Replication::from_secret(&secret), current_region())?;
+    let current = current_secret(&e.secret_id, value_format(&e.secret_id, secret.tags.as_deref())?).await?;
+
+    let config = Config::new_for_secret(&e.secret_id, secret.tags.clone(), current.as_ref())?;
+    logging::set_resource_type(config.resource_type().name());
+    metrics::set_resource_type(config.resource_type().name());
+    info!("rotating secret event={:?} config={:?}", e, config);
+
+    let policy = Policy::from_environment()?;
+    enforce(&policy, &config).await?;
+
+    let resource = new_resource(&config);
+
+    let version_check = check_version_stages(&e, &secret);
+
+    if e.dry_run {
+        return plan(e, &config, resource.as_ref(), version_check).await;
     }
 
-    Ok(())
+    if version_check? == VersionCheck::AlreadyCurrent {
+        return Ok(RotatorOutput::new("already current", e.step, StepOutcome::no_op(vec![e.client_request_token])));
+    }
+
+    // failing rather than succeeding without a pending value lets Secrets Manager end the rotation attempt
+    if e.step == RotatorStep::CreateSecret {
+        if let Some(reason) = check_window(&e, &config.window, Utc::now()).await? {
+            return Err(RotatorError::RotationDeclined {
+                secret_id: e.secret_id.to_string(),
+                step: e.step.name().to_string(),
+                reason,
+            });
+        }
+    }
+
+    let step = e.step;
+    let outcome = match step {
+        RotatorStep::CreateSecret => create_secret(e, &config, resource).await?,
+        RotatorStep::SetSecret => set_secret(e, &config).await?,
+        RotatorStep::TestSecret => test_secret(e, &config).await?,
+        RotatorStep::FinishSecret => finish_secret(e, &config).await?,
+    };
+
+    Ok(RotatorOutput::new("ok", step, outcome))
+}
+
+/// The AWSCURRENT secret, if the secret has a value yet.
+async fn current_secret(secret_id: &str, format: ValueFormat) -> RotatorResult<Option<value::Secret>> {
+    match get_secret_value(secret_id, format, Some(AWSCURRENT), None).await {
+        Ok(value) => Ok(value.secret),
+        Err(RotatorError::SecretValueNotFound { .. }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Describes what a step would do without making any changes.
+///
+/// Version stage check failures are reported rather than returned,
+/// so that a secret can be checked before rotation is set up.
+async fn plan(e: RotatorEvent, config: &Config, resource: &dyn Resource, version_check: RotatorResult<VersionCheck>) -> RotatorResult<RotatorOutput> {
+    let mut plan = vec![];
+
+    match version_check {
+        Ok(VersionCheck::AlreadyCurrent) => {
+            plan.push(format!("version {} is already AWSCURRENT, nothing to do", e.client_request_token));
+        },
+        Ok(VersionCheck::Pending) => {},
+        Err(ref err) => {
+            plan.push(format!("version stage checks would fail: {}", err));
+        },
+    }
+
+    if version_check != Ok(VersionCheck::AlreadyCurrent) {
+        let declined = match e.step {
+            RotatorStep::CreateSecret => check_window(&e, &config.window, Utc::now()).await?,
+            _ => None,
+        };
+
+        match declined {
+            Some(reason) => plan.push(format!("would decline: {}", reason)),
+            None => plan.extend(match e.step {
+                RotatorStep::CreateSecret => plan_create_secret(&e, config, resource).await?,
+                RotatorStep::SetSecret => plan_set_secret(&e, config).await?,
+                RotatorStep::TestSecret => plan_test_secret(&e, config).await?,
+                RotatorStep::FinishSecret => plan_finish_secret(&e, config).await?,
+            }),
+        }
+    }
+
+    for line in plan.iter() {
+        info!("dry run step={:?} secret_id={}: {}", e.step, e.secret_id, line);
+    }
+
+    let outcome = StepOutcome {
+        no_op: version_check == Ok(VersionCheck::AlreadyCurrent),
+        ..Default::default()
+    };
+
+    Ok(RotatorOutput {
+        plan,
+        ..RotatorOutput::new("dry run", e.step, outcome)
+    })
 }
 
 #[cfg(test)]
@@ -155,5 +574,88 @@ mod tests {
         assert_eq!(event1.secret_id, "secret".to_string());
         assert_eq!(event1.client_request_token, "token".to_string());
         assert_eq!(event1.step, RotatorStep::CreateSecret);
+        assert!(!event1.dry_run);
+    }
+
+    #[test]
+    pub fn test_parse_dry_run_input() {
+        let input1 = r#"
+        {
+            "SecretId": "secret",
+            "ClientRequestToken": "token",
+            "Step": "finishSecret",
+            "DryRun": true
+        }
+        "#;
+
+        let event1: RotatorEvent = serde_json::from_str(input1).expect("json parse error");
+
+        assert_eq!(event1.step, RotatorStep::FinishSecret);
+        assert!(event1.dry_run);
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_parse_scheduled_input() {
+        let input = r#"
+        {
+            "version": "0",
+            "id": "53dc4d37-cffa-4f76-80c9-8b7d4a4d2eaa",
+            "detail-type": "Scheduled Event",
+            "source": "aws.events",
+            "account": "123456789012",
+            "time": "2026-07-15T12:00:00Z",
+            "region": "us-east-1",
+            "resources": ["arn:aws:events:us-east-1:123456789012:rule/rotator-sweep"],
+            "detail": {}
+        }
+        "#;
+
+        match RotatorInput::parse(serde_json::from_str(input).expect("json parse error")) {
+            Ok(RotatorInput::Scheduled(e)) => assert_eq!(e.detail_type, "Scheduled Event"),
+            input => panic!("unexpected input {:?}", input),
+        }
+    }
+
+    #[test]
+    pub fn test_output_json() {
+        let output = RotatorOutput::new("ok", RotatorStep::CreateSecret, StepOutcome {
+            no_op: false,
+            changes: vec![ResourceChange::Created { credential_id: "A".to_string() }],
+            versions: vec!["token".to_string()],
+        });
+
+        assert_eq!(serde_json::to_value(&output).unwrap(), serde_json::json!({
+            "message": "ok",
+            "step": "createSecret",
+            "noOp": false,
+            "changes": [{"action": "created", "credentialId": "A"}],
+            "versions": ["token"],
+            "durationMs": 0.0,
+        }));
+
+        let output = RotatorOutput::new("already current", RotatorStep::FinishSecret, StepOutcome::no_op(vec!["token".to_string()]));
+
+        assert_eq!(serde_json::to_value(&output).unwrap(), serde_json::json!({
+            "message": "already current",
+            "step": "finishSecret",
+            "noOp": true,
+            "versions": ["token"],
+            "durationMs": 0.0,
+        }));
+    }
+
+    #[tokio::test]
+    pub async fn test_deadline_exceeded() {
+        let deadline = SystemTime::now() + Duration::from_millis(100);
+
+        let result = with_deadline("secret", RotatorStep::SetSecret, deadline, async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(RotatorOutput::new("ok", RotatorStep::SetSecret, StepOutcome::default()))
+        }).await;
+
+        assert_eq!(result.unwrap_err(), RotatorError::DeadlineExceeded {
+            secret_id: "secret".to_string(),
+            step: "setSecret".to_string(),
+        });
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Map, Value};
+use crate::{RotatorError, RotatorStep};
+
+const NAMESPACE: &str = "Rotator";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct ApiStats {
+    calls: u64,
+    errors: u64,
+    latencies: Vec<f64>,
+}
+
+/// Measurements collected during one invocation.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metrics {
+    resource_type: Option<String>,
+    api_calls: BTreeMap<String, ApiStats>,
+    credential_age: Option<Duration>,
+}
+
+impl Metrics {
+    fn record_api_call(&mut self, api: &str, latency: Duration, success: bool) {
+        let stats = self.api_calls.entry(api.to_string()).or_default();
+        stats.calls += 1;
+        if !success {
+            stats.errors += 1;
+        }
+        stats.latencies.push(millis(latency));
+    }
+}
+
+lazy_static! {
+    static ref METRICS: Mutex<Metrics> = Mutex::new(Metrics::default());
+}
+
+fn update<F: FnOnce(&mut Metrics)>(f: F) {
+    if let Ok(mut metrics) = METRICS.lock() {
+        f(&mut metrics);
+    }
+}
+
+pub fn start_invocation() {
+    update(|metrics| *metrics = Metrics::default());
+}
+
+pub fn set_resource_type(resource_type: &str) {
+    update(|metrics| metrics.resource_type = Some(resource_type.to_string()));
+}
+
+/// Records the age of the credential being replaced.
+pub fn record_credential_age(age: Duration) {
+    update(|metrics| metrics.credential_age = Some(age));
+}
+
+pub fn record_api_call(api: &str, latency: Duration, success: bool) {
+    update(|metrics| metrics.record_api_call(api, latency, success));
+}
+
+/// Calls an AWS API, recording its latency and whether it succeeded.
+pub async fn time_api_call<T, E, F: Future<Output = Result<T, E>>>(api: &str, f: F) -> Result<T, E> {
+    let start = Instant::now();
+    let result = f.await;
+    record_api_call(api, start.elapsed(), result.is_ok());
+    result
+}
+
+/// Writes the invocation's metrics to `w` in CloudWatch Embedded Metric Format, one JSON document per line.
+pub fn emit<W: Write>(w: &mut W, step: RotatorStep, duration: Duration, error: Option<&RotatorError>) -> io::Result<()> {
+    let metrics = METRICS.lock().map(|metrics| metrics.clone()).unwrap_or_default();
+
+    write_documents(w, &metrics, step, duration, error)
+}
+
+fn write_documents<W: Write>(w: &mut W, metrics: &Metrics, step: RotatorStep, duration: Duration, error: Option<&RotatorError>) -> io::Result<()> {
+    for document in documents(metrics, step, duration, error, Utc::now()) {
+        writeln!(w, "{}", document)?;
+    }
+
+    Ok(())
+}
+
+fn documents(metrics: &Metrics, step: RotatorStep, duration: Duration, error: Option<&RotatorError>, timestamp: DateTime<Utc>) -> Vec<Value> {
+    let resource_type = metrics.resource_type.clone().unwrap_or_else(|| "Unknown".to_string());
+    let step = serde_json::to_value(step).unwrap_or(Value::Null);
+    let declined = matches!(error, Some(RotatorError::RotationDeclined { .. }));
+    let failure = error.filter(|_| !declined);
+
+    let mut documents = vec![];
+
+    let mut dimensions = vec![json!(["ResourceType", "Step"])];
+    let mut definitions = vec![
+        json!({"Name": "Duration", "Unit": "Milliseconds"}),
+        json!({"Name": "Success", "Unit": "Count"}),
+        json!({"Name": "Failure", "Unit": "Count"}),
+        json!({"Name": "Declined", "Unit": "Count"}),
+    ];
+    let mut properties = Map::new();
+    properties.insert("ResourceType".to_string(), json!(resource_type));
+    properties.insert("Step".to_string(), step.clone());
+    properties.insert("Duration".to_string(), json!(millis(duration)));
+    properties.insert("Success".to_string(), json!(if error.is_none() { 1 } else { 0 }));
+    properties.insert("Failure".to_string(), json!(if failure.is_some() { 1 } else { 0 }));
+    properties.insert("Declined".to_string(), json!(if declined { 1 } else { 0 }));
+
+    if let Some(error) = failure {
+        dimensions.push(json!(["ResourceType", "Step", "ErrorType"]));
+        properties.insert("ErrorType".to_string(), json!(error.name()));
+    }
+
+    if let Some(age) = metrics.credential_age {
+        definitions.push(json!({"Name": "CredentialAge", "Unit": "Seconds"}));
+        properties.insert("CredentialAge".to_string(), json!(age.as_secs()));
+    }
+
+    documents.push(document(properties, dimensions, definitions, timestamp));
+
+    for (api, stats) in metrics.api_calls.iter() {
+        let mut properties = Map::new();
+        properties.insert("ResourceType".to_string(), json!(resource_type));
+        properties.insert("Step".to_string(), step.clone());
+        properties.insert("Api".to_string(), json!(api));
+        properties.insert("ApiCalls".to_string(), json!(stats.calls));
+        properties.insert("ApiErrors".to_string(), json!(stats.errors));
+        properties.insert("ApiLatency".to_string(), json!(stats.latencies));
+
+        documents.push(document(properties, vec![json!(["ResourceType", "Step", "Api"])], vec![
+            json!({"Name": "ApiCalls", "Unit": "Count"}),
+            json!({"Name": "ApiErrors", "Unit": "Count"}),
+            json!({"Name": "ApiLatency", "Unit": "Milliseconds"}),
+        ], timestamp));
+    }
+
+    documents
+}
+
+fn document(mut properties: Map<String, Value>, dimensions: Vec<Value>, definitions: Vec<Value>, timestamp: DateTime<Utc>) -> Value {
+    properties.insert("_aws".to_string(), json!({
+        "Timestamp": timestamp.timestamp_millis(),
+        "CloudWatchMetrics": [{
+            "Namespace": NAMESPACE,
+            "Dimensions": dimensions,
+            "Metrics": definitions,
+        }],
+    }));
+
+    Value::Object(properties)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    pub fn test_failure_documents() {
+        let mut metrics = Metrics {
+            resource_type: Some("ServiceSpecificCredential".to_string()),
+            credential_age: Some(Duration::from_secs(86400)),
+            ..Default::default()
+        };
+        metrics.api_calls.insert("DescribeSecret".to_string(), ApiStats {
+            calls: 2,
+            errors: 1,
+            latencies: vec![12.0, 30.0],
+        });
+        let error = RotatorError::RotationNotEnabled { secret_id: "secret".to_string() };
+        let timestamp = Utc.with_ymd_and_hms(2026, 7, 15, 12, 0, 0).unwrap();
+
+        let documents = documents(&metrics, RotatorStep::CreateSecret, Duration::from_millis(250), Some(&error), timestamp);
+
+        assert_eq!(documents, vec![
+            json!({
+                "_aws": {
+                    "Timestamp": 1784116800000i64,
+                    "CloudWatchMetrics": [{
+                        "Namespace": "Rotator",
+                        "Dimensions": [["ResourceType", "Step"], ["ResourceType", "Step", "ErrorType"]],
+                        "Metrics": [
+                            {"Name": "Duration", "Unit": "Milliseconds"},
+                            {"Name": "Success", "Unit": "Count"},
+                            {"Name": "Failure", "Unit": "Count"},
+                            {"Name": "Declined", "Unit": "Count"},
+                            {"Name": "CredentialAge", "Unit": "Seconds"},
+                        ],
+                    }],
+                },
+                "ResourceType": "ServiceSpecificCredential",
+                "Step": "createSecret",
+                "Duration": 250.0,
+                "Success": 0,
+                "Failure": 1,
+                "Declined": 0,
+                "ErrorType": "RotationNotEnabled",
+                "CredentialAge": 86400,
+            }),
+            json!({
+                "_aws": {
+                    "Timestamp": 1784116800000i64,
+                    "CloudWatchMetrics": [{
+                        "Namespace": "Rotator",
+                        "Dimensions": [["ResourceType", "Step", "Api"]],
+                        "Metrics": [
+                            {"Name": "ApiCalls", "Unit": "Count"},
+                            {"Name": "ApiErrors", "Unit": "Count"},
+                            {"Name": "ApiLatency", "Unit": "Milliseconds"},
+                        ],
+                    }],
+                },
+                "ResourceType": "ServiceSpecificCredential",
+                "Step": "createSecret",
+                "Api": "DescribeSecret",
+                "ApiCalls": 2,
+                "ApiErrors": 1,
+                "ApiLatency": [12.0, 30.0],
+            }),
+        ]);
+    }
+
+    #[test]
+    pub fn test_declined_documents() {
+        let error = RotatorError::RotationDeclined {
+            secret_id: "secret".to_string(),
+            step: "createSecret".to_string(),
+            reason: "blackout".to_string(),
+        };
+        let timestamp = Utc.with_ymd_and_hms(2026, 7, 15, 12, 0, 0).unwrap();
+
+        let documents = documents(&Metrics::default(), RotatorStep::CreateSecret, Duration::from_millis(250), Some(&error), timestamp);
+
+        assert_eq!(documents[0]["Success"], json!(0));
+        assert_eq!(documents[0]["Failure"], json!(0));
+        assert_eq!(documents[0]["Declined"], json!(1));
+        assert_eq!(documents[0].get("ErrorType"), None);
+        assert_eq!(documents[0]["_aws"]["CloudWatchMetrics"][0]["Dimensions"], json!([["ResourceType", "Step"]]));
+    }
+
+    #[test]
+    pub fn test_emit_writes_json_lines() {
+        // a local buffer, since other tests record into the global metrics while this runs
+        let mut metrics = Metrics::default();
+        metrics.record_api_call("ListServiceSpecificCredentials", Duration::from_millis(5), true);
+
+        let mut stdout = vec![];
+        write_documents(&mut stdout, &metrics, RotatorStep::FinishSecret, Duration::from_millis(10), None).expect("emit error");
+
+        let lines: Vec<Value> = String::from_utf8(stdout).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("json parse error"))
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["Success"], json!(1));
+        assert_eq!(lines[0]["ResourceType"], json!("Unknown"));
+        assert_eq!(lines[1]["Api"], json!("ListServiceSpecificCredentials"));
+    }
+}
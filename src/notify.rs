@@ -0,0 +1,344 @@
+use std::fmt;
+use std::time::Duration;
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::json;
+use crate::{RotatorError, RotatorStep, SNS_CLIENT};
+use crate::drift::{CredentialChange, DriftAction};
+use crate::metrics::time_api_call;
+
+const SNS_TOPIC_ENVIRONMENT_VARIABLE: &str = "ROTATOR_NOTIFY_SNS_TOPIC_ARN";
+const WEBHOOK_ENVIRONMENT_VARIABLE: &str = "ROTATOR_NOTIFY_WEBHOOK_URL";
+const STDOUT_ENVIRONMENT_VARIABLE: &str = "ROTATOR_NOTIFY_STDOUT";
+const ON_SUCCESS_ENVIRONMENT_VARIABLE: &str = "ROTATOR_NOTIFY_ON_SUCCESS";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "outcome")]
+pub enum Outcome {
+    Success,
+    Failure {
+        error_type: String,
+        error_code: String,
+        retryable: bool,
+        message: String,
+        remediation: String,
+    },
+    /// The secret's credential was changed outside the rotator.
+    CredentialChanged {
+        event_name: String,
+        credential_id: String,
+        changed_by: Option<String>,
+        action: DriftAction,
+    },
+}
+
+/// What happened to one rotation step, or to a secret's credential.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Notification {
+    pub secret_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step: Option<RotatorStep>,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+impl Notification {
+    pub fn new(secret_id: &str, step: RotatorStep, error: Option<&RotatorError>) -> Self {
+        Notification {
+            secret_id: secret_id.to_string(),
+            step: Some(step),
+            outcome: match error {
+                None => Outcome::Success,
+                Some(err) => Outcome::Failure {
+                    error_type: err.name().to_string(),
+                    error_code: err.code().to_string(),
+                    retryable: err.is_retryable(),
+                    message: format!("{}", err),
+                    remediation: err.remediation().to_string(),
+                },
+            },
+        }
+    }
+
+    pub fn credential_changed(secret_id: &str, change: &CredentialChange, action: DriftAction) -> Self {
+        Notification {
+            secret_id: secret_id.to_string(),
+            step: None,
+            outcome: Outcome::CredentialChanged {
+                event_name: change.event_name.to_string(),
+                credential_id: change.credential_id.to_string(),
+                changed_by: change.changed_by.clone(),
+                action,
+            },
+        }
+    }
+
+    fn summary(&self, secret: &str) -> String {
+        let step = self.step.map(|step| step.name()).unwrap_or("unknown");
+
+        match self.outcome {
+            Outcome::Success => format!("Rotation step {} succeeded for {}", step, secret),
+            Outcome::Failure { .. } => format!("Rotation step {} failed for {}", step, secret),
+            Outcome::CredentialChanged { ref credential_id, .. } => {
+                format!("Credential {} for {} was changed outside the rotator", credential_id, secret)
+            },
+        }
+    }
+
+    /// A subject SNS accepts, naming the secret rather than giving its ARN, which is in the message.
+    pub fn subject(&self) -> String {
+        sns_subject(&self.summary(secret_name(&self.secret_id)))
+    }
+
+    pub fn text(&self) -> String {
+        let summary = self.summary(&self.secret_id);
+
+        match self.outcome {
+            Outcome::Success => summary,
+            Outcome::Failure { ref error_type, ref remediation, .. } => {
+                format!("{}: {}. {}", summary, error_type, remediation)
+            },
+            Outcome::CredentialChanged { ref event_name, ref changed_by, action, .. } => {
+                format!("{}: {} by {}. {}", summary, event_name, changed_by.as_deref().unwrap_or("unknown"), action.remediation())
+            },
+        }
+    }
+}
+
+/// The secret's name, from an ARN such as `arn:aws:secretsmanager:eu-west-1:123456789012:secret:my-secret-AbCdEf`.
+///
+/// Secrets Manager appends a hyphen and six random characters to the name in the ARN.
+fn secret_name(secret_id: &str) -> &str {
+    match secret_id.strip_prefix("arn:").and_then(|arn| arn.split_once(":secret:")) {
+        Some((_, name)) if name.len() > 7 && name.as_bytes()[name.len() - 7] == b'-' => &name[..name.len() - 7],
+        Some((_, name)) => name,
+        None => secret_id,
+    }
+}
+
+/// SNS subjects must be under 100 printable ASCII characters, or Publish fails.
+const MAX_SUBJECT_LENGTH: usize = 99;
+
+fn sns_subject(subject: &str) -> String {
+    let printable: String = subject.chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() { c } else { '?' })
+        .collect();
+
+    if printable.len() <= MAX_SUBJECT_LENGTH {
+        return printable;
+    }
+
+    format!("{}...", &printable[..MAX_SUBJECT_LENGTH - 3])
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotifyError {
+    Sns {
+        message: String,
+    },
+    Webhook {
+        message: String,
+    },
+    Serialisation {
+        message: String,
+    },
+}
+
+impl fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "notify error: {:?}", self)
+    }
+}
+
+impl std::error::Error for NotifyError {
+}
+
+pub type NotifyResult<T> = std::result::Result<T, NotifyError>;
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, n: &Notification) -> NotifyResult<()>;
+}
+
+/// Prints the notification as a JSON line, mostly useful for testing.
+pub struct StdoutNotifier {
+}
+
+#[async_trait]
+impl Notifier for StdoutNotifier {
+    async fn notify(&self, n: &Notification) -> NotifyResult<()> {
+        let line = serde_json::to_string(n)
+            .map_err(|err| NotifyError::Serialisation {
+                message: format!("{:?}", err),
+            })?;
+
+        println!("{}", line);
+
+        Ok(())
+    }
+}
+
+pub struct SnsNotifier {
+    topic_arn: String,
+}
+
+#[async_trait]
+impl Notifier for SnsNotifier {
+    async fn notify(&self, n: &Notification) -> NotifyResult<()> {
+        let message = serde_json::to_string(n)
+            .map_err(|err| NotifyError::Serialisation {
+                message: format!("{:?}", err),
+            })?;
+
+        time_api_call("Publish", SNS_CLIENT.publish()
+            .topic_arn(&self.topic_arn)
+            .subject(n.subject())
+            .message(message)
+            .send())
+            .await
+            .map_err(|err| NotifyError::Sns {
+                message: format!("{:?}", err),
+            })
+            .map(|_| ())
+    }
+}
+
+/// Posts a Slack-compatible `{"text": ...}` payload.
+///
+/// `ureq` is blocking, so the request runs on tokio's blocking thread pool.
+pub struct WebhookNotifier {
+    url: String,
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, n: &Notification) -> NotifyResult<()> {
+        let timeout = Duration::from_secs(2);
+        let url = self.url.to_string();
+        let payload = json!({ "text": n.text() });
+
+        tokio::task::spawn_blocking(move || ureq::post(&url)
+            .timeout(timeout)
+            .send_json(payload)
+            .map(|_| ())
+            .map_err(|err| format!("{:?}", err)))
+            .await
+            .unwrap_or_else(|err| Err(format!("{:?}", err)))
+            .map_err(|message| NotifyError::Webhook { message })
+    }
+}
+
+/// The notifiers configured in the lambda environment.
+pub struct Notifiers {
+    notifiers: Vec<Box<dyn Notifier>>,
+    on_success: bool,
+}
+
+impl Notifiers {
+    pub fn from_environment() -> Self {
+        let mut notifiers: Vec<Box<dyn Notifier>> = vec![];
+
+        if let Ok(topic_arn) = std::env::var(SNS_TOPIC_ENVIRONMENT_VARIABLE) {
+            notifiers.push(Box::new(SnsNotifier { topic_arn }));
+        }
+        if let Ok(url) = std::env::var(WEBHOOK_ENVIRONMENT_VARIABLE) {
+            notifiers.push(Box::new(WebhookNotifier { url }));
+        }
+        if std::env::var(STDOUT_ENVIRONMENT_VARIABLE).map(|v| v == "true").unwrap_or(false) {
+            notifiers.push(Box::new(StdoutNotifier {}));
+        }
+
+        Notifiers {
+            notifiers,
+            on_success: std::env::var(ON_SUCCESS_ENVIRONMENT_VARIABLE).map(|v| v == "true").unwrap_or(false),
+        }
+    }
+
+    /// Sends the notification to every sink. Failures are logged so they never fail the rotation.
+    pub async fn notify(&self, n: &Notification) {
+        if n.outcome == Outcome::Success && !self.on_success {
+            return;
+        }
+
+        for notifier in self.notifiers.iter() {
+            if let Err(err) = notifier.notify(n).await {
+                error!("error sending notification {:?}: {}", n, err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_failure_notification() {
+        let error = RotatorError::RotationNotEnabled { secret_id: "secret".to_string() };
+        let n = Notification::new("secret", RotatorStep::TestSecret, Some(&error));
+
+        assert_eq!(n.text(), "Rotation step testSecret failed for secret: RotationNotEnabled. Enable rotation on the secret.");
+        assert_eq!(serde_json::to_value(&n).unwrap(), json!({
+            "secret_id": "secret",
+            "step": "testSecret",
+            "outcome": "Failure",
+            "error_type": "RotationNotEnabled",
+            "error_code": "ROTATION_NOT_ENABLED",
+            "retryable": false,
+            "message": "rotation is not enabled for secret secret",
+            "remediation": "Enable rotation on the secret.",
+        }));
+    }
+
+    #[test]
+    pub fn test_sns_subject() {
+        let arn = "arn:aws:secretsmanager:ap-southeast-2:123456789012:secret:prod/platform/codecommit/ci-deploy-user-credentials-AbCdEf";
+        let error = RotatorError::NotSetAsPending { secret_id: arn.to_string(), version: "c8b1f5a4-8a4e-4a8e-9d3f-2b6c1e0f7a9d".to_string() };
+        let n = Notification::new(arn, RotatorStep::CreateSecret, Some(&error));
+
+        assert_eq!(n.subject(), "Rotation step createSecret failed for prod/platform/codecommit/ci-deploy-user-credentials");
+        assert_eq!(serde_json::to_value(&n).unwrap()["secret_id"], json!(arn));
+
+        let long = format!("arn:aws:secretsmanager:eu-west-1:123456789012:secret:{}\u{e9}-AbCdEf", "x".repeat(120));
+        let subject = Notification::new(&long, RotatorStep::FinishSecret, None).subject();
+        assert_eq!(subject.len(), 99);
+        assert!(subject.starts_with("Rotation step finishSecret succeeded for xxx"));
+        assert!(subject.ends_with("xxx..."));
+
+        assert_eq!(sns_subject("failed for caf\u{e9}\n"), "failed for caf??");
+        assert_eq!(secret_name("my-secret"), "my-secret");
+    }
+
+    #[test]
+    pub fn test_success_notification() {
+        let n = Notification::new("secret", RotatorStep::FinishSecret, None);
+
+        assert_eq!(n.text(), "Rotation step finishSecret succeeded for secret");
+        assert_eq!(serde_json::to_value(&n).unwrap(), json!({
+            "secret_id": "secret",
+            "step": "finishSecret",
+            "outcome": "Success",
+        }));
+    }
+
+    #[test]
+    pub fn test_credential_changed_notification() {
+        let change = CredentialChange {
+            event_name: "ResetServiceSpecificCredential".to_string(),
+            credential_id: "ABC".to_string(),
+            status: None,
+            changed_by: Some("arn:aws:iam::123456789012:user/alice".to_string()),
+        };
+        let n = Notification::credential_changed("secret", &change, DriftAction::Alert);
+
+        assert_eq!(n.text(), "Credential ABC for secret was changed outside the rotator: ResetServiceSpecificCredential by arn:aws:iam::123456789012:user/alice. Rotate the secret to replace the credential.");
+        assert_eq!(serde_json::to_value(&n).unwrap(), json!({
+            "secret_id": "secret",
+            "outcome": "CredentialChanged",
+            "event_name": "ResetServiceSpecificCredential",
+            "credential_id": "ABC",
+            "changed_by": "arn:aws:iam::123456789012:user/alice",
+            "action": "Alert",
+        }));
+    }
+}
@@ -1,32 +1,27 @@
-use rusoto_core::RusotoError;
-use rusoto_secretsmanager::{
-    SecretsManager,
-    GetRandomPasswordRequest,
-    GetRandomPasswordError,
-};
-use std::time::Duration;
 use crate::{
     RotatorError,
     RotatorResult,
     SM_CLIENT,
 };
+use crate::metrics::time_api_call;
+use crate::sensitive::Sensitive;
 
-pub fn get_random_password(timeout: Duration) -> RotatorResult<String> {
-    SM_CLIENT.get_random_password(GetRandomPasswordRequest {
-        exclude_characters: Some(r#"/@"'\"#.to_string()),
-        ..Default::default()
-    })
-        .with_timeout(timeout)
-        .sync()
-        .map_err(|e| match e {
-            RusotoError::Service(GetRandomPasswordError::InvalidParameter(msg)) => {
+pub async fn get_random_password() -> RotatorResult<Sensitive<String>> {
+    time_api_call("GetRandomPassword", SM_CLIENT.get_random_password()
+        .exclude_characters(r#"/@"'\"#)
+        .send())
+        .await
+        .map_err(|e| match e.as_service_error() {
+            Some(err) if err.is_invalid_parameter_exception() => {
                 RotatorError::InvalidPasswordParameter {
-                    message: msg,
+                    message: err.meta().message().unwrap_or_default().to_string(),
                 }
             },
-            e => RotatorError::GetRandomPassword(format!("{:?}", e))
+            _ => RotatorError::from_sdk("GetRandomPassword", &e).unwrap_or_else(|| RotatorError::GetRandomPassword(format!("{:?}", e)))
         })
         .and_then(|response| {
-            response.random_password.ok_or(RotatorError::GetRandomPassword("missing password in response".to_string()))
+            response.random_password
+                .map(Sensitive::new)
+                .ok_or(RotatorError::GetRandomPassword("missing password in response".to_string()))
         })
-}
\ No newline at end of file
+}
@@ -0,0 +1,214 @@
+use serde::Deserialize;
+use crate::config::{Config, ResourceConfig};
+use crate::error::{RotatorResult, RotatorError};
+use crate::iam::get_user_path;
+
+const POLICY_ENVIRONMENT_VARIABLE: &str = "ROTATOR_POLICY";
+
+/// Limits which resources a secret's config may point the rotator at.
+///
+/// Each allow list is optional and permits everything when absent.
+/// Deny lists take precedence over allow lists.
+/// Patterns may use `*` to match any run of characters and `?` to match a single character.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Policy {
+    pub allowed_resource_types: Option<Vec<String>>,
+    pub allowed_user_names: Option<Vec<String>>,
+    #[serde(default)]
+    pub denied_user_names: Vec<String>,
+    pub allowed_user_paths: Option<Vec<String>>,
+    #[serde(default)]
+    pub denied_user_paths: Vec<String>,
+    pub allowed_service_names: Option<Vec<String>>,
+    #[serde(default)]
+    pub denied_service_names: Vec<String>,
+}
+
+impl Policy {
+    /// Reads the JSON policy document from the `ROTATOR_POLICY` lambda environment variable.
+    /// Without it every config is permitted.
+    pub fn from_environment() -> RotatorResult<Self> {
+        match std::env::var(POLICY_ENVIRONMENT_VARIABLE) {
+            Ok(document) => Self::from_document(&document),
+            Err(_) => Ok(Policy::default()),
+        }
+    }
+
+    pub fn from_document(document: &str) -> RotatorResult<Self> {
+        serde_json::from_str(document)
+            .map_err(|err| RotatorError::InvalidPolicy {
+                message: format!("policy document deserialisation error: {:?}", err),
+            })
+    }
+
+    /// Checks the parts of the config that don't need to look anything up in IAM.
+    pub fn check(&self, config: &Config) -> RotatorResult<()> {
+        let secret_id = &config.secret_id;
+
+        check_rule(secret_id, "resourceType", config.resource_type().name(), self.allowed_resource_types.as_ref(), &[], matches)?;
+
+        match config.resource {
+            ResourceConfig::ServiceSpecificCredential(ref cfg) => {
+                // IAM user names are unique ignoring case, so CI-ADMIN is the same user as ci-admin
+                check_rule(secret_id, "userName", &cfg.user_name, self.allowed_user_names.as_ref(), &self.denied_user_names, matches_ignore_case)?;
+                check_rule(secret_id, "serviceName", &cfg.service_name, self.allowed_service_names.as_ref(), &self.denied_service_names, matches)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    pub fn restricts_user_paths(&self) -> bool {
+        self.allowed_user_paths.is_some() || !self.denied_user_paths.is_empty()
+    }
+
+    pub fn check_user_path(&self, secret_id: &str, path: &str) -> RotatorResult<()> {
+        check_rule(secret_id, "userPath", path, self.allowed_user_paths.as_ref(), &self.denied_user_paths, matches)
+    }
+}
+
+/// Checks the config against the policy, looking up the IAM user's path if the policy restricts paths.
+pub async fn enforce(policy: &Policy, config: &Config) -> RotatorResult<()> {
+    policy.check(config)?;
+
+    match config.resource {
+        ResourceConfig::ServiceSpecificCredential(ref cfg) if policy.restricts_user_paths() => {
+            let path = get_user_path(&cfg.user_name)
+                .await
+                .map_err(|err| RotatorError::from_iam(&config.secret_id, "get user error", err))?;
+
+            policy.check_user_path(&config.secret_id, &path)
+        },
+        _ => Ok(()),
+    }
+}
+
+fn check_rule(secret_id: &str, rule: &str, value: &str, allowed: Option<&Vec<String>>, denied: &[String], matcher: fn(&str, &str) -> bool) -> RotatorResult<()> {
+    let is_denied = denied.iter().any(|pattern| matcher(pattern, value));
+    let is_allowed = allowed.map(|patterns| patterns.iter().any(|pattern| matcher(pattern, value))).unwrap_or(true);
+
+    if is_denied || !is_allowed {
+        error!("policy violation secret_id={} rule={} value={}", secret_id, rule, value);
+
+        return Err(RotatorError::PolicyViolation {
+            secret_id: secret_id.to_string(),
+            rule: rule.to_string(),
+            value: value.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Matches a glob pattern where `*` matches any run of characters and `?` matches exactly one.
+pub fn matches(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack = None;
+
+    while v < value.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == value[v]) {
+            p += 1;
+            v += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, v));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            v = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Matches a glob pattern ignoring case.
+pub fn matches_ignore_case(pattern: &str, value: &str) -> bool {
+    matches(&pattern.to_lowercase(), &value.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::config::{AdoptionMode, ServiceSpecificCredentialConfig};
+    use crate::replica::ReplicaMode;
+    use crate::value::ValueFormat;
+    use crate::window::RotationWindow;
+
+    fn config(user_name: &str, service_name: &str) -> Config {
+        Config {
+            secret_id: "secret".to_string(),
+            resource: ResourceConfig::ServiceSpecificCredential(ServiceSpecificCredentialConfig {
+                user_name: user_name.to_string(),
+                service_name: service_name.to_string(),
+                adoption_mode: AdoptionMode::Refuse,
+            }),
+            templates: BTreeMap::new(),
+            window: RotationWindow::default(),
+            finish_labels: vec![],
+            value_format: ValueFormat::Json,
+            replica_mode: ReplicaMode::Ignore,
+        }
+    }
+
+    #[test]
+    pub fn test_matches() {
+        assert!(matches("*", ""));
+        assert!(matches("ci-*", "ci-bob"));
+        assert!(matches("/automation/*/", "/automation/git/"));
+        assert!(matches("b?b", "bob"));
+        assert!(!matches("ci-*", "bob"));
+        assert!(!matches("b?b", "boob"));
+    }
+
+    #[test]
+    pub fn test_empty_policy_permits_everything() {
+        assert_eq!(Policy::from_document("{}").unwrap().check(&config("Admin", "codecommit.amazonaws.com")), Ok(()));
+    }
+
+    #[test]
+    pub fn test_policy_rules() {
+        let policy = Policy::from_document(r#"
+        {
+            "allowedUserNames": ["ci-*"],
+            "deniedUserNames": ["ci-admin"],
+            "allowedServiceNames": ["codecommit.amazonaws.com"]
+        }
+        "#).expect("policy parse error");
+
+        assert_eq!(policy.check(&config("ci-bob", "codecommit.amazonaws.com")), Ok(()));
+        assert_eq!(policy.check(&config("ci-admin", "codecommit.amazonaws.com")), Err(RotatorError::PolicyViolation {
+            secret_id: "secret".to_string(),
+            rule: "userName".to_string(),
+            value: "ci-admin".to_string(),
+        }));
+        assert_eq!(policy.check(&config("CI-ADMIN", "codecommit.amazonaws.com")), Err(RotatorError::PolicyViolation {
+            secret_id: "secret".to_string(),
+            rule: "userName".to_string(),
+            value: "CI-ADMIN".to_string(),
+        }));
+        assert_eq!(policy.check(&config("CI-Bob", "codecommit.amazonaws.com")), Ok(()));
+        assert_eq!(policy.check(&config("Admin", "codecommit.amazonaws.com")), Err(RotatorError::PolicyViolation {
+            secret_id: "secret".to_string(),
+            rule: "userName".to_string(),
+            value: "Admin".to_string(),
+        }));
+        assert_eq!(policy.check(&config("ci-bob", "cassandra.amazonaws.com")), Err(RotatorError::PolicyViolation {
+            secret_id: "secret".to_string(),
+            rule: "serviceName".to_string(),
+            value: "cassandra.amazonaws.com".to_string(),
+        }));
+    }
+
+    #[test]
+    pub fn test_invalid_policy_document() {
+        assert!(matches!(Policy::from_document(r#"{"allowedUsers": []}"#), Err(RotatorError::InvalidPolicy { .. })));
+    }
+}
@@ -0,0 +1,269 @@
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::fmt;
+use std::time::Duration;
+use aws_config::Region;
+use aws_sdk_secretsmanager::operation::describe_secret::DescribeSecretOutput;
+use aws_sdk_secretsmanager::types::StatusType;
+use crate::{RotatorError, RotatorResult, sdk_config};
+use crate::describe::describe_secret;
+use crate::metrics::time_api_call;
+
+/// How long testSecret waits between checks of the replicas.
+const REPLICA_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How many times testSecret checks the replicas before giving up, about two minutes in all.
+const REPLICA_POLL_ATTEMPTS: u32 = 24;
+
+/// Whether testSecret waits for the secret's replicas to have the new version before the rotation finishes.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum ReplicaMode {
+    #[default]
+    Ignore,
+    Wait,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplicaModeParseError(String);
+
+impl StdError for ReplicaModeParseError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for ReplicaModeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "replica mode parse error: {}", self.0)
+    }
+}
+
+impl TryFrom<&str> for ReplicaMode {
+    type Error = ReplicaModeParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "Ignore" => Ok(ReplicaMode::Ignore),
+            "Wait" => Ok(ReplicaMode::Wait),
+            s => Err(ReplicaModeParseError(format!("invalid replica mode '{}'", s)))
+        }
+    }
+}
+
+/// A region the secret is replicated to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replica {
+    pub region: String,
+    pub status: Option<StatusType>,
+}
+
+impl Replica {
+    /// Failed replicas were never created, so they never receive new versions.
+    pub fn failed(&self) -> bool {
+        self.status == Some(StatusType::Failed)
+    }
+}
+
+/// Where a secret is replicated, as returned by DescribeSecret.
+///
+/// Describing a replica gives the primary region and no replicas.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replication {
+    pub primary_region: Option<String>,
+    pub replicas: Vec<Replica>,
+}
+
+impl Replication {
+    pub fn from_secret(secret: &DescribeSecretOutput) -> Self {
+        Replication {
+            primary_region: secret.primary_region.clone(),
+            replicas: secret.replication_status().iter()
+                .filter_map(|status| status.region.as_ref().map(|region| Replica {
+                    region: region.to_string(),
+                    status: status.status.clone(),
+                }))
+                .collect(),
+        }
+    }
+
+    /// Whether the secret described from `region` is a replica of a secret in another region.
+    pub fn is_replica_in(&self, region: Option<&str>) -> bool {
+        match (self.primary_region.as_deref(), region) {
+            (Some(primary_region), Some(region)) => primary_region != region,
+            _ => false,
+        }
+    }
+
+    /// The regions new versions are replicated to.
+    pub fn replica_regions(&self) -> Vec<&str> {
+        self.replicas.iter()
+            .map(|replica| &replica.region[..])
+            .collect()
+    }
+
+    /// The regions whose replicas failed, which will never have the new version.
+    pub fn failed_regions(&self) -> Vec<&str> {
+        self.replicas.iter()
+            .filter(|replica| replica.failed())
+            .map(|replica| &replica.region[..])
+            .collect()
+    }
+}
+
+/// Rejects waiting on replicas that failed, since they'll never have the version.
+pub fn check_replicas(secret_id: &str, version: &str, replication: &Replication) -> RotatorResult<()> {
+    let failed = replication.failed_regions();
+    if !failed.is_empty() {
+        return Err(RotatorError::ReplicationIncomplete {
+            secret_id: secret_id.to_string(),
+            version: version.to_string(),
+            regions: failed.iter().map(|region| region.to_string()).collect(),
+            failed: true,
+        });
+    }
+
+    Ok(())
+}
+
+/// Rejects rotating a replica, which Secrets Manager overwrites with the primary's versions.
+pub fn check_primary(secret_id: &str, replication: &Replication, region: Option<&str>) -> RotatorResult<()> {
+    if replication.is_replica_in(region) {
+        return Err(RotatorError::ReplicaSecret {
+            secret_id: secret_id.to_string(),
+            region: region.unwrap_or_default().to_string(),
+            primary_region: replication.primary_region.clone().unwrap_or_default(),
+        });
+    }
+
+    Ok(())
+}
+
+/// The region the rotator runs in, which is where the secrets it's invoked for are described.
+pub fn current_region() -> Option<&'static str> {
+    sdk_config().region().map(|region| region.as_ref())
+}
+
+fn regional_client(region: &str) -> aws_sdk_secretsmanager::Client {
+    aws_sdk_secretsmanager::Client::from_conf(aws_sdk_secretsmanager::config::Builder::from(sdk_config())
+        .region(Region::new(region.to_string()))
+        .build())
+}
+
+/// Whether the replica in the region has the version yet. A replica that's still being created doesn't.
+async fn replica_has_version(secret_name: &str, region: &str, version: &str) -> RotatorResult<bool> {
+    let result = time_api_call("DescribeSecret", regional_client(region).describe_secret()
+        .secret_id(secret_name)
+        .send())
+        .await;
+
+    match result {
+        Ok(secret) => Ok(secret.version_ids_to_stages.map(|versions| versions.contains_key(version)).unwrap_or(false)),
+        Err(e) => match e.as_service_error() {
+            Some(err) if err.is_resource_not_found_exception() => Ok(false),
+            _ => Err(RotatorError::from_sdk("DescribeSecret", &e).unwrap_or_else(|| RotatorError::DescribeSecretError(format!("{:?}", e)))),
+        },
+    }
+}
+
+/// Waits until every replica of the secret has the version, returning the replica regions.
+///
+/// Replicas are described by name, since the secret's ARN names the primary region.
+/// The primary is described again on each check, so a replica that fails while we wait stops the wait.
+pub async fn wait_for_replicas(secret_id: &str, version: &str) -> RotatorResult<Vec<String>> {
+    let mut caught_up: Vec<String> = vec![];
+    let mut regions = vec![];
+
+    for attempt in 1..=REPLICA_POLL_ATTEMPTS {
+        let secret = describe_secret(secret_id).await?;
+        let replication = Replication::from_secret(&secret);
+        let secret_name = secret.name.as_deref().unwrap_or(secret_id);
+        check_replicas(secret_id, version, &replication)?;
+
+        regions = replication.replica_regions().iter().map(|region| region.to_string()).collect();
+
+        let pending: Vec<String> = regions.iter().filter(|region| !caught_up.contains(region)).cloned().collect();
+        let mut behind = vec![];
+        for region in pending {
+            if replica_has_version(secret_name, &region, version).await? {
+                caught_up.push(region);
+            } else {
+                behind.push(region);
+            }
+        }
+
+        if behind.is_empty() {
+            return Ok(regions);
+        }
+
+        if attempt == REPLICA_POLL_ATTEMPTS {
+            return Err(RotatorError::ReplicationIncomplete {
+                secret_id: secret_id.to_string(),
+                version: version.to_string(),
+                regions: behind,
+                failed: false,
+            });
+        }
+
+        info!("waiting for replicas of secret {} in [{}] to have version {}", secret_id, behind.join(", "), version);
+        tokio::time::sleep(REPLICA_POLL_INTERVAL).await;
+    }
+
+    Ok(regions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_secretsmanager::types::ReplicationStatusType;
+
+    fn replica(region: &str, status: StatusType) -> ReplicationStatusType {
+        ReplicationStatusType::builder()
+            .region(region)
+            .status(status)
+            .build()
+    }
+
+    #[test]
+    pub fn test_replication() {
+        let primary = Replication::from_secret(&DescribeSecretOutput::builder()
+            .primary_region("eu-west-1")
+            .replication_status(replica("us-east-1", StatusType::InSync))
+            .replication_status(replica("ap-south-1", StatusType::Failed))
+            .replication_status(replica("eu-central-1", StatusType::InProgress))
+            .build());
+
+        assert!(!primary.is_replica_in(Some("eu-west-1")));
+        assert_eq!(primary.replica_regions(), vec!["us-east-1", "ap-south-1", "eu-central-1"]);
+        assert_eq!(primary.failed_regions(), vec!["ap-south-1"]);
+        assert_eq!(check_primary("secret", &primary, Some("eu-west-1")), Ok(()));
+        assert_eq!(check_replicas("secret", "v2", &primary), Err(RotatorError::ReplicationIncomplete {
+            secret_id: "secret".to_string(),
+            version: "v2".to_string(),
+            regions: vec!["ap-south-1".to_string()],
+            failed: true,
+        }));
+
+        let replica = Replication::from_secret(&DescribeSecretOutput::builder()
+            .primary_region("eu-west-1")
+            .build());
+
+        assert!(replica.is_replica_in(Some("us-east-1")));
+        assert!(replica.replica_regions().is_empty());
+        assert_eq!(check_replicas("secret", "v2", &replica), Ok(()));
+        assert_eq!(check_primary("secret", &replica, Some("us-east-1")), Err(RotatorError::ReplicaSecret {
+            secret_id: "secret".to_string(),
+            region: "us-east-1".to_string(),
+            primary_region: "eu-west-1".to_string(),
+        }));
+
+        // secrets that were never replicated may not report a primary region
+        assert!(!Replication::default().is_replica_in(Some("us-east-1")));
+    }
+
+    #[test]
+    pub fn test_parse_replica_mode() {
+        assert_eq!(ReplicaMode::try_from("Wait"), Ok(ReplicaMode::Wait));
+        assert_eq!(ReplicaMode::try_from("Ignore"), Ok(ReplicaMode::Ignore));
+        assert!(ReplicaMode::try_from("wait").is_err());
+    }
+}
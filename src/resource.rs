@@ -1,8 +1,14 @@
-use std::time::Duration;
+use async_trait::async_trait;
+use aws_sdk_secretsmanager::types::Tag;
+use chrono::{DateTime, Utc};
+use crate::{metrics, SM_CLIENT};
+use crate::describe::describe_secret;
+use crate::metrics::time_api_call;
 use crate::error::{RotatorResult, RotatorError};
 use crate::passwd::get_random_password;
-use crate::config::Config;
+use crate::config::{AdoptionMode, Config, ResourceConfig, ServiceSpecificCredentialConfig};
 use crate::iam::{
+    CredentialMetadata,
     CredentialStatus,
     list_service_specific_credentials,
     create_service_specific_credential,
@@ -10,16 +16,33 @@ use crate::iam::{
     update_service_specific_credential,
 };
 use crate::value::{
+    ResourceChange,
     Secret,
     SecretValue,
 };
 
 const MAX_SERVICE_SPECIFIC_CREDENTIALS: usize = 2;
 
-pub trait Resource {
-    fn create_new_password(&self, current_value: SecretValue) -> RotatorResult<Secret>;
-    fn set_password(&self, s: Secret) -> RotatorResult<()>;
-    fn test_password(&self, s: Secret) -> RotatorResult<()>;
+/// Names the rotation that is creating a credential, and the credentials the user had before, as `<token> <id> <id>`.
+pub const PENDING_CREDENTIAL_TAG: &str = "rotator:pendingCredential";
+
+#[async_trait]
+pub trait Resource: Send + Sync {
+    /// Describes what `create_new_password` would do, without changing anything.
+    async fn plan_new_password(&self, token: &str, current_value: &SecretValue) -> RotatorResult<Vec<String>>;
+    /// The new secret for the rotation with the client request token, with the changes made to the resource for the audit record.
+    async fn create_new_password(&self, token: &str, current_value: SecretValue) -> RotatorResult<(Secret, Vec<ResourceChange>)>;
+    async fn set_password(&self, s: Secret) -> RotatorResult<()>;
+    async fn test_password(&self, s: Secret) -> RotatorResult<()>;
+    /// Makes the credential in an earlier version of the secret usable again, for a rollback.
+    async fn restore_password(&self, s: &Secret) -> RotatorResult<Vec<ResourceChange>>;
+}
+
+/// The resource rotated by a secret with this config.
+pub fn new_resource(config: &Config) -> Box<dyn Resource> {
+    match config.resource {
+        ResourceConfig::ServiceSpecificCredential(ref cfg) => Box::new(ServiceSpecificCredentialResource::new(&config.secret_id, cfg)),
+    }
 }
 
 pub struct GenericResource {
@@ -32,25 +55,32 @@ impl GenericResource {
     }
 }
 
+#[async_trait]
 impl Resource for GenericResource {
+    async fn plan_new_password(&self, _token: &str, _current_value: &SecretValue) -> RotatorResult<Vec<String>> {
+        Ok(vec!["would generate a random password".to_string()])
+    }
 
-    fn create_new_password(&self, _current_value: SecretValue) -> RotatorResult<Secret> {
-        let timeout = Duration::from_secs(2);
-        let password = get_random_password(timeout)?;
+    async fn create_new_password(&self, _token: &str, _current_value: SecretValue) -> RotatorResult<(Secret, Vec<ResourceChange>)> {
+        let password = get_random_password().await?;
         
-        Ok(Secret {
+        Ok((Secret {
             password: Some(password),
             ..Default::default()
-        })
+        }, vec![]))
     }
 
-    fn set_password(&self, _s: Secret) -> RotatorResult<()> {
+    async fn set_password(&self, _s: Secret) -> RotatorResult<()> {
         unimplemented!()
     }
 
-    fn test_password(&self, _s: Secret) -> RotatorResult<()> {
+    async fn test_password(&self, _s: Secret) -> RotatorResult<()> {
         unimplemented!()
     }
+
+    async fn restore_password(&self, _s: &Secret) -> RotatorResult<Vec<ResourceChange>> {
+        Ok(vec![])
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -58,102 +88,361 @@ pub struct ServiceSpecificCredentialResource {
     secret_id: String,
     user_name: String,
     service_name: String,
+    adoption_mode: AdoptionMode,
 }
 
 impl ServiceSpecificCredentialResource {
-    pub fn new(cfg: &Config) -> RotatorResult<Self> {
-        let service_name = cfg.service_name.as_ref().ok_or_else(|| RotatorError::InvalidConfig {
-            secret_id: cfg.secret_id.to_string(),
-            message: format!("missing service name for service specific credential"),
-        })?;
+    pub fn new(secret_id: &str, cfg: &ServiceSpecificCredentialConfig) -> Self {
+        ServiceSpecificCredentialResource {
+            secret_id: secret_id.to_string(),
+            user_name: cfg.user_name.to_string(),
+            service_name: cfg.service_name.to_string(),
+            adoption_mode: cfg.adoption_mode,
+        }
+    }
 
-        let user_name = cfg.user_name.as_ref().ok_or_else(|| RotatorError::InvalidConfig {
-            secret_id: cfg.secret_id.to_string(),
-            message: format!("missing resource arn for service specific credential"),
-        })?;
-        
-        Ok(ServiceSpecificCredentialResource {
-            secret_id: cfg.secret_id.to_string(),
-            user_name: user_name.to_string(),
-            service_name: service_name.to_string(),
-        })      
+    /// Activates or deactivates the credential, returning the change for the audit record.
+    async fn update_status(&self, credential_id: &str, status: CredentialStatus) -> RotatorResult<Vec<ResourceChange>> {
+        info!("updating service specific credential id={} user_name={} status={:?}", credential_id, self.user_name, status);
+
+        update_service_specific_credential(credential_id, Some(&self.user_name), status.clone())
+            .await
+            .map_err(|err| RotatorError::from_iam(&self.secret_id, "update service specific credential error", err))?;
+
+        Ok(status_change(credential_id, &status).into_iter().collect())
+    }
+
+    /// The credentials an earlier attempt at this rotation created, which it had no chance to record as managed.
+    async fn created_by_rotation(&self, token: &str, creds: &[CredentialMetadata]) -> RotatorResult<Vec<String>> {
+        let secret = describe_secret(&self.secret_id).await?;
+
+        let pending = secret.tags().iter()
+            .find(|tag| tag.key() == Some(PENDING_CREDENTIAL_TAG))
+            .and_then(|tag| tag.value());
+
+        Ok(pending.map(|value| created_since(value, token, creds)).unwrap_or_default())
+    }
+
+    /// Records which credentials existed before creating one, so that a retried createSecret knows the new one is its own.
+    async fn record_pending_credential(&self, token: &str, existing: &[String]) -> RotatorResult<()> {
+        let mut value = vec![token.to_string()];
+        value.extend(existing.iter().cloned());
+
+        time_api_call("TagResource", SM_CLIENT.tag_resource()
+            .secret_id(&self.secret_id)
+            .tags(Tag::builder()
+                .key(PENDING_CREDENTIAL_TAG)
+                .value(value.join(" "))
+                .build())
+            .send())
+            .await
+            .map_err(|e| RotatorError::from_sdk("TagResource", &e).unwrap_or_else(|| RotatorError::TagResource(format!("{:?}", e))))
+            .map(|_| ())
     }
 }
 
-impl Resource for ServiceSpecificCredentialResource {
-    fn create_new_password(&self, current_value: SecretValue) -> RotatorResult<Secret> {
-        let timeout = Duration::from_secs(2);
-        
-        let creds = list_service_specific_credentials(Some(&self.user_name), Some(&self.service_name), timeout)
-            .map_err(|err| RotatorError::IamError {
-                secret_id: self.secret_id.to_string(),
-                message: format!("list service specific credential error: {:?}", err),
-            })?;
+/// The ids of the credentials missing from the `rotator:pendingCredential` tag value, if it was written for the token.
+fn created_since(value: &str, token: &str, creds: &[CredentialMetadata]) -> Vec<String> {
+    let mut words = value.split(' ');
+
+    if words.next() != Some(token) {
+        return vec![];
+    }
+    let existing: Vec<&str> = words.collect();
+
+    creds.iter()
+        .map(|cred| &cred.service_specific_credential_id)
+        .filter(|id| !existing.contains(&id.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// How setting the credential's status changes it, if the status is one the audit record knows.
+fn status_change(credential_id: &str, status: &CredentialStatus) -> Option<ResourceChange> {
+    let credential_id = credential_id.to_string();
+
+    match status {
+        CredentialStatus::Active => Some(ResourceChange::Activated { credential_id }),
+        CredentialStatus::Inactive => Some(ResourceChange::Deactivated { credential_id }),
+        CredentialStatus::Unknown(_) => None,
+    }
+}
+
+/// Works out which of the user's existing credentials the rotator manages.
+///
+/// The current credential is always managed, even if createSecret couldn't recover the ids
+/// of a secret rotated before they were recorded. Anything else on the user needs adopting.
+/// Nothing is managed before the first rotation.
+fn managed_credential_ids(current: Option<&Secret>, creds: &[CredentialMetadata]) -> Vec<String> {
+    let current = match current {
+        Some(secret) => secret,
+        None => return vec![],
+    };
+
+    let current_id = match current.service_specific_credential_id {
+        Some(ref id) => id,
+        None => return vec![],
+    };
+
+    creds.iter()
+        .map(|cred| &cred.service_specific_credential_id)
+        .filter(|id| *id == current_id || current.managed_credential_ids.contains(id))
+        .cloned()
+        .collect()
+}
+
+fn record_current_credential_age(current: Option<&Secret>, creds: &[CredentialMetadata]) {
+    let current_id = current.and_then(|secret| secret.service_specific_credential_id.as_ref());
+
+    let created = creds.iter()
+        .find(|cred| Some(&cred.service_specific_credential_id) == current_id)
+        .and_then(|cred| DateTime::parse_from_rfc3339(&cred.create_date).ok());
+
+    if let Some(created) = created {
+        if let Ok(age) = Utc::now().signed_duration_since(created).to_std() {
+            metrics::record_credential_age(age);
+        }
+    }
+}
+
+/// The change to make to the user's credentials to produce a new password.
+#[derive(Debug, Clone, PartialEq)]
+enum CredentialAction {
+    /// Create a credential, alongside the existing ones.
+    Create {
+        existing: Vec<String>,
+    },
+    Reset {
+        credential_id: String,
+        inactive: bool,
+    },
+}
+
+impl ServiceSpecificCredentialResource {
+    /// Decides which credential to create or reset without changing anything in IAM.
+    ///
+    /// Also returns the managed credential ids to record, including any adopted ones.
+    /// Credentials created by an earlier attempt with the same token are managed.
+    async fn plan(&self, token: &str, current_value: &SecretValue) -> RotatorResult<(CredentialAction, Vec<String>)> {
+        let creds = list_service_specific_credentials(Some(&self.user_name), Some(&self.service_name))
+            .await
+            .map_err(|err| RotatorError::from_iam(&self.secret_id, "list service specific credential error", err))?;
         info!("found {} service specific credentials: {:?}", creds.len(), creds);
-        
-        let mut cred;
-        
+
+        let mut managed = managed_credential_ids(current_value.secret.as_ref(), &creds);
+        for id in self.created_by_rotation(token, &creds).await? {
+            if !managed.contains(&id) {
+                info!("service specific credential id={} was created by an earlier attempt at this rotation", id);
+                managed.push(id);
+            }
+        }
+        info!("managed service specific credentials: {:?}", managed);
+
+        record_current_credential_age(current_value.secret.as_ref(), &creds);
+
         if creds.len() < MAX_SERVICE_SPECIFIC_CREDENTIALS {
-            info!("creating service specific credential user_name={} service_name={}", self.user_name, self.service_name);
+            let existing = creds.iter().map(|cred| cred.service_specific_credential_id.to_string()).collect();
+            return Ok((CredentialAction::Create { existing }, managed));
+        }
+
+        let cred_to_reset = if let Some(credential_id) = current_value.secret.as_ref()
+            .and_then(|secret| secret.service_specific_credential_id.as_ref()) {
 
-            cred = create_service_specific_credential(&self.user_name, &self.service_name, timeout)
-                .map_err(|err| RotatorError::IamError {
-                    secret_id: self.secret_id.to_string(),
-                    message: format!("create service specific credential error: {:?}", err)
-                })?;
+            creds.iter().find(|cred| &cred.service_specific_credential_id != credential_id)
         } else {
-            let cred_to_reset = if let Some(credential_id) = current_value.secret.as_ref()
-                .and_then(|secret| secret.service_specific_credential_id.as_ref()) {
+            creds.iter().min_by(|a, b| a.create_date.cmp(&b.create_date))
+        };
 
-                creds.iter().find(|cred| &cred.service_specific_credential_id != credential_id)
-            } else {
-                creds.get(0)
-            };
+        // shouldn't fail. there should be at least one credential that isn't current
+        let cred_to_reset = cred_to_reset.ok_or_else(|| RotatorError::Other {
+            message: "no empty credential slots to rotate".to_string(),
+        })?;
 
-            if let Some(cred_to_reset) = cred_to_reset {
-                info!("reseting service specific credential id={} user_name={}", cred_to_reset.service_specific_credential_id, cred_to_reset.service_user_name);
+        if !managed.contains(&cred_to_reset.service_specific_credential_id) {
+            let unmanaged: Vec<String> = creds.iter()
+                .map(|cred| cred.service_specific_credential_id.to_string())
+                .filter(|id| !managed.contains(id))
+                .collect();
 
-                cred = reset_service_specific_credential(&cred_to_reset.service_specific_credential_id, Some(&self.user_name), timeout)
-                    .map_err(|err| RotatorError::IamError {
+            match self.adoption_mode {
+                AdoptionMode::Adopt => {
+                    info!("adopting service specific credentials ids={:?} user_name={}", unmanaged, self.user_name);
+                    managed.extend(unmanaged);
+                },
+                AdoptionMode::Refuse => {
+                    error!("refusing to reset unmanaged service specific credentials ids={:?} user_name={}", unmanaged, self.user_name);
+                    return Err(RotatorError::UnmanagedCredentials {
                         secret_id: self.secret_id.to_string(),
-                        message: format!("reset service specific credential error: {:?}", err)
-                    })?;
-                
-                if cred.status == CredentialStatus::Inactive {
-                    info!("activating service specific credential id={} user_name={}", cred.service_specific_credential_id, cred.service_user_name);
-
-                    update_service_specific_credential(&cred.service_specific_credential_id, Some(&self.user_name), CredentialStatus::Active, timeout)
-                        .map_err(|err| RotatorError::IamError {
-                            secret_id: self.secret_id.to_string(),
-                            message: format!("update service specific credential error: {:?}", err)
-                        })?;
-                    
-                    cred.status = CredentialStatus::Active;
-                }
-            } else {
-                // shouldn't get here. there should be at least one credential that isn't current
-                return Err(RotatorError::Other {
-                    message: format!("no empty credential slots to rotate")
-                });
+                        user_name: self.user_name.to_string(),
+                        credential_ids: unmanaged,
+                        message: "delete the unmanaged credentials or tag the secret with rotator:adoptionMode=Adopt".to_string(),
+                    });
+                },
             }
         }
 
+        Ok((CredentialAction::Reset {
+            credential_id: cred_to_reset.service_specific_credential_id.to_string(),
+            inactive: cred_to_reset.status == CredentialStatus::Inactive,
+        }, managed))
+    }
+}
+
+#[async_trait]
+impl Resource for ServiceSpecificCredentialResource {
+    async fn plan_new_password(&self, token: &str, current_value: &SecretValue) -> RotatorResult<Vec<String>> {
+        let (action, managed) = self.plan(token, current_value).await?;
+
+        let mut plan = vec![];
+        match action {
+            CredentialAction::Create { .. } => {
+                plan.push(format!("would create a {} credential for user {}", self.service_name, self.user_name));
+            },
+            CredentialAction::Reset { credential_id, inactive } => {
+                plan.push(format!("would reset credential {} for user {}", credential_id, self.user_name));
+                if inactive {
+                    plan.push(format!("would activate credential {} for user {}", credential_id, self.user_name));
+                }
+            },
+        }
+        plan.push(format!("would record managed credentials {:?}", managed));
+
+        Ok(plan)
+    }
+
+    async fn create_new_password(&self, token: &str, current_value: SecretValue) -> RotatorResult<(Secret, Vec<ResourceChange>)> {
+        let (action, mut managed) = self.plan(token, &current_value).await?;
+
+        let mut cred = match &action {
+            CredentialAction::Create { existing } => {
+                self.record_pending_credential(token, existing).await?;
+
+                info!("creating service specific credential user_name={} service_name={}", self.user_name, self.service_name);
+
+                create_service_specific_credential(&self.user_name, &self.service_name)
+                    .await
+                    .map_err(|err| RotatorError::from_iam(&self.secret_id, "create service specific credential error", err))?
+            },
+            CredentialAction::Reset { credential_id, .. } => {
+                info!("reseting service specific credential id={} user_name={}", credential_id, self.user_name);
+
+                reset_service_specific_credential(credential_id, Some(&self.user_name))
+                    .await
+                    .map_err(|err| RotatorError::from_iam(&self.secret_id, "reset service specific credential error", err))?
+            },
+        };
+
+        let credential_id = cred.service_specific_credential_id.to_string();
+        let mut changes = vec![match action {
+            CredentialAction::Create { .. } => ResourceChange::Created { credential_id },
+            CredentialAction::Reset { .. } => ResourceChange::Reset { credential_id },
+        }];
+
+        if cred.status == CredentialStatus::Inactive {
+            changes.extend(self.update_status(&cred.service_specific_credential_id, CredentialStatus::Active).await?);
+            cred.status = CredentialStatus::Active;
+        }
+
+        if !managed.contains(&cred.service_specific_credential_id) {
+            managed.push(cred.service_specific_credential_id.to_string());
+        }
+
         let mut secret = current_value.secret.clone().unwrap_or(Secret::default());
 
         secret.username = Some(cred.service_user_name);
         secret.password = Some(cred.service_password);
         secret.service_specific_credential_id = Some(cred.service_specific_credential_id);
+        secret.managed_credential_ids = managed;
 
-        Ok(secret)
+        Ok((secret, changes))
     }
 
-    fn set_password(&self, _s: Secret) -> RotatorResult<()> {
+    async fn set_password(&self, _s: Secret) -> RotatorResult<()> {
         info!("nothing to do to set password");
         Ok(())
     }
 
-    fn test_password(&self, _s: Secret) -> RotatorResult<()> {
+    async fn test_password(&self, _s: Secret) -> RotatorResult<()> {
         info!("test_password unimplemented");
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Reactivates the credential if it was deactivated. A credential that has been reset or deleted can't be restored.
+    async fn restore_password(&self, s: &Secret) -> RotatorResult<Vec<ResourceChange>> {
+        let credential_id = s.service_specific_credential_id.as_ref().ok_or_else(|| RotatorError::RollbackUnavailable {
+            secret_id: self.secret_id.to_string(),
+            reason: "the version to restore doesn't name a credential".to_string(),
+        })?;
+
+        let creds = list_service_specific_credentials(Some(&self.user_name), Some(&self.service_name))
+            .await
+            .map_err(|err| RotatorError::from_iam(&self.secret_id, "list service specific credential error", err))?;
+
+        let cred = creds.iter()
+            .find(|cred| &cred.service_specific_credential_id == credential_id)
+            .ok_or_else(|| RotatorError::IamEntityNotFound {
+                secret_id: self.secret_id.to_string(),
+                message: format!("credential {} for user {} no longer exists", credential_id, self.user_name),
+            })?;
+
+        if cred.status == CredentialStatus::Active {
+            return Ok(vec![]);
+        }
+
+        self.update_status(credential_id, CredentialStatus::Active).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::metadata;
+
+    #[test]
+    pub fn test_nothing_managed_on_first_rotation() {
+        let creds = vec![metadata("A", CredentialStatus::Active), metadata("B", CredentialStatus::Active)];
+
+        assert!(managed_credential_ids(None, &creds).is_empty());
+        assert!(managed_credential_ids(Some(&Secret::default()), &creds).is_empty());
+    }
+
+    #[test]
+    pub fn test_legacy_secret_manages_only_current_credential() {
+        let creds = vec![metadata("A", CredentialStatus::Active), metadata("B", CredentialStatus::Active)];
+        let secret = Secret {
+            service_specific_credential_id: Some("A".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(managed_credential_ids(Some(&secret), &creds), vec!["A".to_string()]);
+    }
+
+    #[test]
+    pub fn test_recorded_credentials_are_managed() {
+        let creds = vec![metadata("A", CredentialStatus::Active), metadata("B", CredentialStatus::Active)];
+        let secret = Secret {
+            service_specific_credential_id: Some("A".to_string()),
+            managed_credential_ids: vec!["A".to_string(), "C".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(managed_credential_ids(Some(&secret), &creds), vec!["A".to_string()]);
+    }
+
+    #[test]
+    pub fn test_status_change() {
+        assert_eq!(status_change("A", &CredentialStatus::Active), Some(ResourceChange::Activated { credential_id: "A".to_string() }));
+        assert_eq!(status_change("A", &CredentialStatus::Inactive), Some(ResourceChange::Deactivated { credential_id: "A".to_string() }));
+        assert_eq!(status_change("A", &CredentialStatus::Unknown("Expired".to_string())), None);
+    }
+
+    #[test]
+    pub fn test_created_since() {
+        let creds = vec![metadata("A", CredentialStatus::Active), metadata("B", CredentialStatus::Active)];
+
+        assert_eq!(created_since("t1 A", "t1", &creds), vec!["B".to_string()]);
+        assert_eq!(created_since("t1", "t1", &creds), vec!["A".to_string(), "B".to_string()]);
+        assert!(created_since("t0 A", "t1", &creds).is_empty());
+        assert!(created_since("t1 A B", "t1", &creds).is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+use serde::Serialize;
+use crate::{RotatorError, RotatorResult};
+use crate::config::{Config, value_format};
+use crate::describe::describe_secret;
+use crate::finish::update_secret_version_stage;
+use crate::policy::{Policy, enforce};
+use crate::replica::{Replication, check_primary, current_region};
+use crate::resource::new_resource;
+use crate::stage::{StageMove, VersionStages, AWSCURRENT};
+use crate::value::{get_secret_value, ResourceChange};
+
+/// Marks the version that was rolled back from, so that it can be cleaned up and isn't restored by a second rollback.
+pub const ROLLED_BACK_LABEL: &str = "ROTATOR_ROLLED_BACK";
+
+/// Lease token held while rolling back, so that rotation steps can't run at the same time.
+pub const ROLLBACK_TOKEN: &str = "rollback";
+
+/// The versions involved in a rollback and the label moves that make it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RollbackPlan {
+    pub restore_version: String,
+    pub bad_version: String,
+    pub moves: Vec<StageMove>,
+}
+
+/// Plans moving AWSCURRENT back to the AWSPREVIOUS version.
+///
+/// The bad version is labelled first, so that if moving AWSCURRENT fails a retry still knows which version was bad.
+pub fn plan_rollback(secret_id: &str, stages: &VersionStages) -> RotatorResult<RollbackPlan> {
+    let unavailable = |reason: String| RotatorError::RollbackUnavailable {
+        secret_id: secret_id.to_string(),
+        reason,
+    };
+
+    let bad_version = stages.current().ok_or_else(|| unavailable("it has no AWSCURRENT version".to_string()))?;
+    let restore_version = stages.previous().ok_or_else(|| unavailable("it has no AWSPREVIOUS version".to_string()))?;
+
+    if restore_version == bad_version {
+        return Err(unavailable(format!("version {} is both AWSCURRENT and AWSPREVIOUS", bad_version)));
+    }
+    if stages.has_label(restore_version, ROLLED_BACK_LABEL) {
+        return Err(unavailable(format!("AWSPREVIOUS version {} has already been rolled back from", restore_version)));
+    }
+
+    Ok(RollbackPlan {
+        restore_version: restore_version.to_string(),
+        bad_version: bad_version.to_string(),
+        moves: vec![
+            StageMove {
+                label: ROLLED_BACK_LABEL.to_string(),
+                to_version: bad_version.to_string(),
+                from_version: stages.version(ROLLED_BACK_LABEL).map(|v| v.to_string()),
+            },
+            StageMove {
+                label: AWSCURRENT.to_string(),
+                to_version: restore_version.to_string(),
+                from_version: Some(bad_version.to_string()),
+            },
+        ],
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RollbackReport {
+    pub secret_id: String,
+    pub restored_version: String,
+    pub rolled_back_version: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<ResourceChange>,
+}
+
+/// Restores the AWSPREVIOUS version of the secret, reactivating its credential if needed.
+pub async fn rollback(secret_id: &str) -> RotatorResult<RollbackReport> {
+    let secret = describe_secret(secret_id).await?;
+    check_primary(secret_id, &Replication::from_secret(&secret), current_region())?;
+    let plan = plan_rollback(secret_id, &VersionStages::from_secret(&secret))?;
+    info!("rolling back secret {} from version {} to version {}", secret_id, plan.bad_version, plan.restore_version);
+
+    let format = value_format(secret_id, secret.tags.as_deref())?;
+    let restore = get_secret_value(secret_id, format, None, Some(&plan.restore_version)).await?
+        .secret
+        .ok_or_else(|| RotatorError::RollbackUnavailable {
+            secret_id: secret_id.to_string(),
+            reason: format!("version {} has no value", plan.restore_version),
+        })?;
+
+    let config = Config::new_for_secret(secret_id, secret.tags.clone(), Some(&restore))?;
+    enforce(&Policy::from_environment()?, &config).await?;
+    let changes = new_resource(&config).restore_password(&restore).await?;
+
+    for stage_move in plan.moves.iter() {
+        update_secret_version_stage(secret_id, stage_move).await?;
+    }
+
+    info!("rolled back secret {} to version {} changes={:?}", secret_id, plan.restore_version, changes);
+
+    Ok(RollbackReport {
+        secret_id: secret_id.to_string(),
+        restored_version: plan.restore_version,
+        rolled_back_version: plan.bad_version,
+        changes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::stages;
+    use crate::stage::AWSPREVIOUS;
+
+    fn reason(result: RotatorResult<RollbackPlan>) -> String {
+        match result {
+            Err(RotatorError::RollbackUnavailable { reason, .. }) => reason,
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    pub fn test_plan_rollback() {
+        let before = stages(&[("v1", &[AWSPREVIOUS]), ("v2", &[AWSCURRENT])]);
+        let plan = plan_rollback("secret", &before).expect("rollback error");
+
+        assert_eq!(plan.restore_version, "v1");
+        assert_eq!(plan.bad_version, "v2");
+
+        let after = before.apply(&plan.moves);
+
+        assert_eq!(after, stages(&[("v1", &[AWSCURRENT]), ("v2", &[ROLLED_BACK_LABEL, AWSPREVIOUS])]));
+        assert_eq!(reason(plan_rollback("secret", &after)), "AWSPREVIOUS version v2 has already been rolled back from");
+    }
+
+    #[test]
+    pub fn test_rollback_unavailable() {
+        assert_eq!(reason(plan_rollback("secret", &stages(&[("v1", &[AWSCURRENT])]))), "it has no AWSPREVIOUS version");
+        assert_eq!(reason(plan_rollback("secret", &stages(&[]))), "it has no AWSCURRENT version");
+    }
+}
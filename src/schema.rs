@@ -0,0 +1,252 @@
+use crate::{RotatorError, RotatorResult};
+use crate::config::ResourceType;
+use crate::sensitive::Sensitive;
+use crate::value::Secret;
+
+/// The schema version written into every secret value the rotator creates.
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+/// Upgrades a secret value from one schema version to the next.
+struct Migration {
+    from: u32,
+    description: &'static str,
+    migrate: fn(&mut Secret),
+}
+
+/// Every migration in order, one per schema version before the current one.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "values written before schema versioning have the same fields as version 1",
+        migrate: |_| {},
+    },
+    Migration {
+        from: 1,
+        description: "drops rotation_changes, since audit records no longer keep the changes in the value",
+        migrate: |secret| secret.attributes.retain(|name, _| !name.eq_ignore_ascii_case("rotation_changes")),
+    },
+];
+
+/// The schema version of the value. Values written before versioning have none, which is version 0.
+pub fn schema_version(secret: &Secret) -> u32 {
+    secret.schema_version.unwrap_or(0)
+}
+
+/// Rejects values written by a newer rotator, which may have fields this one would drop or misread.
+pub fn check_schema_version(secret_id: &str, secret: &Secret) -> RotatorResult<()> {
+    let version = schema_version(secret);
+
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(RotatorError::UnsupportedSchemaVersion {
+            secret_id: secret_id.to_string(),
+            version,
+        });
+    }
+
+    Ok(())
+}
+
+/// The version each migration that would bring the value up to the current schema version starts from, with its description.
+pub fn pending_migrations(secret: &Secret) -> Vec<(u32, &'static str)> {
+    let version = schema_version(secret);
+
+    MIGRATIONS.iter()
+        .filter(|m| m.from >= version)
+        .map(|m| (m.from, m.description))
+        .collect()
+}
+
+/// Upgrades the value to the current schema version, in place.
+pub fn migrate(secret_id: &str, secret: &mut Secret) -> RotatorResult<()> {
+    check_schema_version(secret_id, secret)?;
+
+    let version = schema_version(secret);
+
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= version) {
+        info!("migrating secret {} from schema version {}: {}", secret_id, migration.from, migration.description);
+        (migration.migrate)(secret);
+    }
+
+    secret.schema_version = Some(CURRENT_SCHEMA_VERSION);
+
+    Ok(())
+}
+
+/// The credentials a value written before `managed_credential_ids` was recorded manages.
+///
+/// The rotator alternates between the user's two credentials, so the previous version names the other one it created.
+/// Values that already record their credentials, or name none, are left to `managed_credential_ids` as they are.
+pub fn legacy_managed_credential_ids(current: &Secret, previous: Option<&Secret>) -> Vec<String> {
+    if !current.managed_credential_ids.is_empty() {
+        return vec![];
+    }
+
+    let mut ids: Vec<String> = current.service_specific_credential_id.iter().cloned().collect();
+    if ids.is_empty() {
+        return ids;
+    }
+
+    if let Some(id) = previous.and_then(|secret| secret.service_specific_credential_id.as_ref()) {
+        if !ids.contains(id) {
+            ids.push(id.to_string());
+        }
+    }
+
+    ids
+}
+
+/// The fields a service specific credential secret has after it has been rotated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceSpecificCredentialPayload {
+    pub username: String,
+    pub password: Sensitive<String>,
+    pub service_specific_credential_id: String,
+    pub managed_credential_ids: Vec<String>,
+}
+
+/// The typed fields of a secret value for its resource type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Payload {
+    ServiceSpecificCredential(ServiceSpecificCredentialPayload),
+}
+
+impl Payload {
+    /// Reads the fields the resource type needs, reporting every missing one.
+    pub fn from_secret(secret_id: &str, resource_type: ResourceType, secret: &Secret) -> RotatorResult<Self> {
+        let mut missing = vec![];
+
+        if secret.username.is_none() {
+            missing.push("username");
+        }
+        if secret.password.is_none() {
+            missing.push("password");
+        }
+
+        match resource_type {
+            ResourceType::ServiceSpecificCredential => {
+                if secret.service_specific_credential_id.is_none() {
+                    missing.push("service_specific_credential_id");
+                }
+
+                match (&secret.username, &secret.password, &secret.service_specific_credential_id) {
+                    (Some(username), Some(password), Some(credential_id)) => Ok(Payload::ServiceSpecificCredential(ServiceSpecificCredentialPayload {
+                        username: username.to_string(),
+                        password: password.clone(),
+                        service_specific_credential_id: credential_id.to_string(),
+                        managed_credential_ids: secret.managed_credential_ids.clone(),
+                    })),
+                    _ => Err(RotatorError::SerialisationError {
+                        secret_id: secret_id.to_string(),
+                        message: format!("{} secret value is missing {}", resource_type.name(), missing.join(", ")),
+                    }),
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    pub fn test_schema_version_round_trip() {
+        let legacy: Secret = serde_json::from_value(json!({"username": "bob", "password": "pw"})).unwrap();
+        assert_eq!(schema_version(&legacy), 0);
+        assert!(serde_json::to_value(&legacy).unwrap().get("rotatorSchemaVersion").is_none());
+
+        let current: Secret = serde_json::from_value(json!({"username": "bob", "rotatorSchemaVersion": 1})).unwrap();
+        assert_eq!(schema_version(&current), 1);
+        assert_eq!(serde_json::to_value(&current).unwrap()["rotatorSchemaVersion"], json!(1));
+        assert!(current.attributes.is_empty());
+    }
+
+    #[test]
+    pub fn test_migrate() {
+        let mut secret: Secret = serde_json::from_value(json!({"username": "bob", "password": "pw", "url": "https://example.com"})).unwrap();
+
+        assert_eq!(pending_migrations(&secret).len(), 2);
+        migrate("secret", &mut secret).expect("migration error");
+
+        assert_eq!(schema_version(&secret), CURRENT_SCHEMA_VERSION);
+        assert!(pending_migrations(&secret).is_empty());
+        assert_eq!(serde_json::to_value(&secret).unwrap(), json!({
+            "username": "bob",
+            "password": "pw",
+            "url": "https://example.com",
+            "rotatorSchemaVersion": CURRENT_SCHEMA_VERSION,
+        }));
+    }
+
+    #[test]
+    pub fn test_migrate_drops_rotation_changes() {
+        let mut secret: Secret = serde_json::from_value(json!({
+            "username": "bob",
+            "password": "pw",
+            "rotation_changes": [{"action": "reset", "credentialId": "A"}],
+            "rotatorSchemaVersion": 1,
+        })).unwrap();
+
+        assert_eq!(pending_migrations(&secret).len(), 1);
+        migrate("secret", &mut secret).expect("migration error");
+
+        assert!(secret.attributes.is_empty());
+        assert_eq!(schema_version(&secret), CURRENT_SCHEMA_VERSION);
+    }
+
+    #[test]
+    pub fn test_future_schema_version_rejected() {
+        let mut secret: Secret = serde_json::from_value(json!({"username": "bob", "rotatorSchemaVersion": CURRENT_SCHEMA_VERSION + 1})).unwrap();
+
+        let expected = Err(RotatorError::UnsupportedSchemaVersion {
+            secret_id: "secret".to_string(),
+            version: CURRENT_SCHEMA_VERSION + 1,
+        });
+        assert_eq!(check_schema_version("secret", &secret), expected);
+        assert_eq!(migrate("secret", &mut secret), expected);
+        assert_eq!(schema_version(&secret), CURRENT_SCHEMA_VERSION + 1);
+    }
+
+    #[test]
+    pub fn test_legacy_managed_credential_ids() {
+        let legacy: Secret = serde_json::from_value(json!({"username": "bob-at-123", "password": "pw", "service_specific_credential_id": "B"})).unwrap();
+        let previous: Secret = serde_json::from_value(json!({"username": "bob-at-123", "password": "old", "service_specific_credential_id": "A"})).unwrap();
+
+        assert_eq!(legacy_managed_credential_ids(&legacy, Some(&previous)), vec!["B".to_string(), "A".to_string()]);
+        assert_eq!(legacy_managed_credential_ids(&legacy, None), vec!["B".to_string()]);
+        assert_eq!(legacy_managed_credential_ids(&legacy, Some(&Secret::default())), vec!["B".to_string()]);
+
+        let recorded = Secret {
+            managed_credential_ids: vec!["B".to_string()],
+            ..legacy.clone()
+        };
+        assert!(legacy_managed_credential_ids(&recorded, Some(&previous)).is_empty());
+        assert!(legacy_managed_credential_ids(&Secret::default(), Some(&previous)).is_empty());
+    }
+
+    #[test]
+    pub fn test_payload() {
+        let secret: Secret = serde_json::from_value(json!({
+            "username": "bob-at-123",
+            "password": "pw",
+            "service_specific_credential_id": "A",
+            "managed_credential_ids": ["A", "B"],
+        })).unwrap();
+
+        match Payload::from_secret("secret", ResourceType::ServiceSpecificCredential, &secret) {
+            Ok(Payload::ServiceSpecificCredential(payload)) => {
+                assert_eq!(payload.service_specific_credential_id, "A");
+                assert_eq!(payload.managed_credential_ids, vec!["A".to_string(), "B".to_string()]);
+            },
+            other => panic!("unexpected payload {:?}", other),
+        }
+
+        let secret: Secret = serde_json::from_value(json!({"username": "bob-at-123"})).unwrap();
+
+        assert_eq!(Payload::from_secret("secret", ResourceType::ServiceSpecificCredential, &secret), Err(RotatorError::SerialisationError {
+            secret_id: "secret".to_string(),
+            message: "ServiceSpecificCredential secret value is missing password, service_specific_credential_id".to_string(),
+        }));
+    }
+}
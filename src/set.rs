@@ -1,14 +1,14 @@
-use std::time::Duration;
 use crate::{
     RotatorEvent,
     RotatorResult,
+    StepOutcome,
 };
-use crate::value::get_secret_value;
+use crate::config::Config;
+use crate::value::{get_secret_value, plan_read_pending};
+use crate::stage::AWSPENDING;
 
-pub fn set_secret(e: RotatorEvent) -> RotatorResult<()> {
-    let timeout = Duration::from_secs(2);
-
-    let _value = match get_secret_value(&e.secret_id, Some("AWSPENDING"), Some(&e.client_request_token), timeout) {
+pub async fn set_secret(e: RotatorEvent, config: &Config) -> RotatorResult<StepOutcome> {
+    let _value = match get_secret_value(&e.secret_id, config.value_format, Some(AWSPENDING), Some(&e.client_request_token)).await {
         Ok(value) => {
             info!("setSecret: Successfully retrieved secret for {}.", e.secret_id);
             Ok(value)
@@ -21,6 +21,15 @@ pub fn set_secret(e: RotatorEvent) -> RotatorResult<()> {
 
     // todo: set secret on resource
 
-    
-    Ok(())
-}
\ No newline at end of file
+
+    Ok(StepOutcome {
+        versions: vec![e.client_request_token],
+        ..Default::default()
+    })
+}
+
+pub async fn plan_set_secret(e: &RotatorEvent, config: &Config) -> RotatorResult<Vec<String>> {
+    let read = plan_read_pending(&e.secret_id, config.value_format, &e.client_request_token).await?;
+
+    Ok(vec![format!("{}, setSecret makes no other changes", read)])
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::fmt;
+use aws_sdk_secretsmanager::operation::describe_secret::DescribeSecretOutput;
+
+pub const AWSCURRENT: &str = "AWSCURRENT";
+pub const AWSPENDING: &str = "AWSPENDING";
+pub const AWSPREVIOUS: &str = "AWSPREVIOUS";
+
+/// Labels managed by Secrets Manager, which can't be used as custom labels.
+const RESERVED_LABELS: &[&str] = &[AWSCURRENT, AWSPENDING, AWSPREVIOUS];
+
+/// Staging labels may be up to 256 characters long.
+const MAX_LABEL_LENGTH: usize = 256;
+
+/// Moving `label` onto `to_version`, taking it off `from_version` if another version has it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StageMove {
+    pub label: String,
+    pub to_version: String,
+    pub from_version: Option<String>,
+}
+
+/// The staging labels on each version of a secret, as returned by DescribeSecret.
+///
+/// Each label is on at most one version.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VersionStages {
+    versions: HashMap<String, Vec<String>>,
+}
+
+impl VersionStages {
+    pub fn new(versions: HashMap<String, Vec<String>>) -> Self {
+        VersionStages { versions }
+    }
+
+    pub fn from_secret(secret: &DescribeSecretOutput) -> Self {
+        VersionStages::new(secret.version_ids_to_stages.clone().unwrap_or_default())
+    }
+
+    /// The version with the label, if any.
+    pub fn version(&self, label: &str) -> Option<&str> {
+        self.versions.iter()
+            .find(|(_, labels)| labels.iter().any(|l| l == label))
+            .map(|(version, _)| &version[..])
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.version(AWSCURRENT)
+    }
+
+    pub fn previous(&self) -> Option<&str> {
+        self.version(AWSPREVIOUS)
+    }
+
+    /// The labels on the version, or None if the version has no labels.
+    pub fn labels(&self, version: &str) -> Option<&[String]> {
+        self.versions.get(version).map(|labels| &labels[..])
+    }
+
+    pub fn has_label(&self, version: &str, label: &str) -> bool {
+        self.labels(version).map(|labels| labels.iter().any(|l| l == label)).unwrap_or(false)
+    }
+
+    /// The moves that make `version` current, skipping any label it already has.
+    ///
+    /// Extra labels come first and AWSCURRENT last, so that if a move fails the version isn't yet current
+    /// and a retried finishSecret makes the remaining moves.
+    pub fn promote(&self, version: &str, extra_labels: &[String]) -> Vec<StageMove> {
+        extra_labels.iter()
+            .map(|label| &label[..])
+            .chain(std::iter::once(AWSCURRENT))
+            .filter(|label| !self.has_label(version, label))
+            .map(|label| StageMove {
+                label: label.to_string(),
+                to_version: version.to_string(),
+                from_version: self.version(label).map(|v| v.to_string()),
+            })
+            .collect()
+    }
+
+    /// The stages after making the moves.
+    ///
+    /// Secrets Manager moves AWSPREVIOUS to the version AWSCURRENT is taken from, so that happens here too.
+    pub fn apply(&self, moves: &[StageMove]) -> VersionStages {
+        let mut stages = self.clone();
+
+        for m in moves {
+            if m.label == AWSCURRENT {
+                if let Some(ref from_version) = m.from_version {
+                    stages.move_label(AWSPREVIOUS, from_version);
+                }
+            }
+            stages.move_label(&m.label, &m.to_version);
+        }
+
+        stages
+    }
+
+    fn move_label(&mut self, label: &str, version: &str) {
+        for labels in self.versions.values_mut() {
+            labels.retain(|l| l != label);
+        }
+        self.versions.entry(version.to_string()).or_default().push(label.to_string());
+        self.versions.retain(|_, labels| !labels.is_empty());
+    }
+}
+
+/// Custom labels moved to the new version when a rotation finishes, e.g. `ROTATOR_LAST_GOOD`.
+///
+/// Parsed from a space separated list, since tag values may not contain commas.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StagingLabels(pub Vec<String>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StagingLabelsParseError(String);
+
+impl StdError for StagingLabelsParseError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for StagingLabelsParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "staging labels parse error: {}", self.0)
+    }
+}
+
+impl TryFrom<&str> for StagingLabels {
+    type Error = StagingLabelsParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mut labels: Vec<String> = vec![];
+
+        for label in value.split_whitespace() {
+            if RESERVED_LABELS.contains(&label) {
+                return Err(StagingLabelsParseError(format!("{} is managed by Secrets Manager", label)));
+            }
+            if label.len() > MAX_LABEL_LENGTH {
+                return Err(StagingLabelsParseError(format!("{} is longer than {} characters", label, MAX_LABEL_LENGTH)));
+            }
+            if !labels.iter().any(|l| l == label) {
+                labels.push(label.to_string());
+            }
+        }
+
+        Ok(StagingLabels(labels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::stages;
+
+    #[test]
+    pub fn test_version_stages() {
+        let stages = stages(&[
+            ("v1", &[AWSPREVIOUS]),
+            ("v2", &[AWSCURRENT, "ROTATOR_LAST_GOOD"]),
+            ("v3", &[AWSPENDING]),
+        ]);
+
+        assert_eq!(stages.current(), Some("v2"));
+        assert_eq!(stages.version(AWSPENDING), Some("v3"));
+        assert_eq!(stages.previous(), Some("v1"));
+        assert_eq!(stages.version("ROTATOR_LAST_GOOD"), Some("v2"));
+        assert!(stages.has_label("v3", AWSPENDING));
+        assert!(!stages.has_label("v4", AWSPENDING));
+        assert_eq!(stages.labels("v4"), None);
+    }
+
+    #[test]
+    pub fn test_promote() {
+        let before = stages(&[
+            ("v1", &[AWSPREVIOUS]),
+            ("v2", &[AWSCURRENT, "ROTATOR_LAST_GOOD"]),
+            ("v3", &[AWSPENDING]),
+        ]);
+
+        let moves = before.promote("v3", &["ROTATOR_LAST_GOOD".to_string()]);
+
+        assert_eq!(moves, vec![
+            StageMove { label: "ROTATOR_LAST_GOOD".to_string(), to_version: "v3".to_string(), from_version: Some("v2".to_string()) },
+            StageMove { label: AWSCURRENT.to_string(), to_version: "v3".to_string(), from_version: Some("v2".to_string()) },
+        ]);
+
+        assert_eq!(before.apply(&moves), stages(&[
+            ("v2", &[AWSPREVIOUS]),
+            ("v3", &[AWSPENDING, "ROTATOR_LAST_GOOD", AWSCURRENT]),
+        ]));
+    }
+
+    #[test]
+    pub fn test_promote_first_and_partial() {
+        // the first rotation of a secret created without a value has no current version
+        let before = stages(&[("v1", &[AWSPENDING])]);
+        let moves = before.promote("v1", &[]);
+
+        assert_eq!(moves, vec![StageMove { label: AWSCURRENT.to_string(), to_version: "v1".to_string(), from_version: None }]);
+        assert_eq!(before.apply(&moves).previous(), None);
+
+        // a retry after the custom label moved but AWSCURRENT didn't
+        let before = stages(&[("v1", &[AWSCURRENT]), ("v2", &[AWSPENDING, "ROTATOR_LAST_GOOD"])]);
+
+        assert_eq!(before.promote("v2", &["ROTATOR_LAST_GOOD".to_string()]), vec![
+            StageMove { label: AWSCURRENT.to_string(), to_version: "v2".to_string(), from_version: Some("v1".to_string()) },
+        ]);
+        assert!(before.apply(&before.promote("v1", &[])).promote("v1", &[]).is_empty());
+    }
+
+    #[test]
+    pub fn test_parse_staging_labels() {
+        assert_eq!(StagingLabels::try_from("ROTATOR_LAST_GOOD  OTHER ROTATOR_LAST_GOOD"), Ok(StagingLabels(vec![
+            "ROTATOR_LAST_GOOD".to_string(),
+            "OTHER".to_string(),
+        ])));
+        assert_eq!(StagingLabels::try_from(""), Ok(StagingLabels(vec![])));
+        assert!(StagingLabels::try_from("AWSPREVIOUS").is_err());
+    }
+}
@@ -0,0 +1,304 @@
+use aws_sdk_secretsmanager::primitives::DateTime as SdkDateTime;
+use aws_sdk_secretsmanager::types::{RotationRulesType, SecretListEntry};
+use chrono::{DateTime, Duration, SecondsFormat, TimeZone, Utc};
+use serde::{Serialize, Deserialize};
+use tokio::task::JoinSet;
+use crate::{RotatorResult, current_secret};
+use crate::config::{Config, ResourceConfig, RESOURCE_TYPE_TAG, value_format};
+use crate::iam::{CredentialMetadata, CredentialStatus, list_service_specific_credentials};
+use crate::list::list_secrets_with_tag;
+use crate::value::Secret;
+
+/// How many secrets are checked at once.
+const CONCURRENCY: usize = 4;
+
+/// How late a rotation may be before it's reported as overdue.
+const GRACE_HOURS: i64 = 24;
+
+/// An EventBridge scheduled event, which starts a sweep.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ScheduledEvent {
+    #[serde(rename="detail-type")]
+    pub detail_type: String,
+
+    pub source: String,
+
+    #[serde(default)]
+    pub time: Option<String>,
+}
+
+/// A problem found with one secret.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "check", rename_all = "camelCase")]
+pub enum Finding {
+    RotationDisabled,
+    #[serde(rename_all = "camelCase")]
+    RotationOverdue {
+        last_rotated: Option<String>,
+        due: String,
+    },
+    InvalidConfig {
+        message: String,
+    },
+    /// The AWSCURRENT value doesn't name a credential, e.g. because it has never been rotated.
+    NoCredential,
+    #[serde(rename_all = "camelCase")]
+    CredentialMissing {
+        credential_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    CredentialInactive {
+        credential_id: String,
+    },
+    /// A check couldn't be made, e.g. because an AWS call failed.
+    CheckFailed {
+        message: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretReport {
+    pub secret_id: String,
+    pub findings: Vec<Finding>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SweepReport {
+    pub checked: usize,
+    pub failing: usize,
+    pub secrets: Vec<SecretReport>,
+}
+
+impl SweepReport {
+    fn new(mut secrets: Vec<SecretReport>) -> Self {
+        secrets.sort_by(|a, b| a.secret_id.cmp(&b.secret_id));
+
+        SweepReport {
+            checked: secrets.len(),
+            failing: secrets.iter().filter(|secret| !secret.findings.is_empty()).count(),
+            secrets,
+        }
+    }
+}
+
+/// Checks every secret tagged with `rotator:resourceType`.
+pub async fn sweep(now: DateTime<Utc>) -> RotatorResult<SweepReport> {
+    let entries = list_secrets_with_tag(RESOURCE_TYPE_TAG).await?;
+    info!("sweeping {} secrets", entries.len());
+
+    let mut tasks = JoinSet::new();
+    let mut reports = vec![];
+
+    for entry in entries {
+        if tasks.len() >= CONCURRENCY {
+            reports.extend(join_next(&mut tasks).await);
+        }
+        tasks.spawn(check_secret(entry, now));
+    }
+    while !tasks.is_empty() {
+        reports.extend(join_next(&mut tasks).await);
+    }
+
+    let report = SweepReport::new(reports);
+    for secret in report.secrets.iter() {
+        for finding in secret.findings.iter() {
+            warn!("sweep finding secret_id={} finding={:?}", secret.secret_id, finding);
+        }
+    }
+    info!("sweep checked {} secrets, {} failing", report.checked, report.failing);
+
+    Ok(report)
+}
+
+async fn join_next(tasks: &mut JoinSet<SecretReport>) -> Option<SecretReport> {
+    match tasks.join_next().await? {
+        Ok(report) => Some(report),
+        Err(err) => {
+            error!("sweep check failed: {:?}", err);
+            None
+        },
+    }
+}
+
+async fn check_secret(entry: SecretListEntry, now: DateTime<Utc>) -> SecretReport {
+    let secret_id = entry.arn.clone().or_else(|| entry.name.clone()).unwrap_or_default();
+
+    let mut findings = vec![];
+
+    if !entry.rotation_enabled.unwrap_or(false) {
+        findings.push(Finding::RotationDisabled);
+    } else {
+        let last_rotated = entry.last_rotated_date.as_ref().and_then(timestamp);
+        let created = entry.created_date.as_ref().and_then(timestamp);
+        findings.extend(check_schedule(last_rotated, created, entry.rotation_rules.as_ref(), now));
+    }
+
+    findings.extend(check_resource(&secret_id, entry.tags).await);
+
+    SecretReport {
+        secret_id,
+        findings,
+    }
+}
+
+async fn check_resource(secret_id: &str, tags: Option<Vec<aws_sdk_secretsmanager::types::Tag>>) -> Option<Finding> {
+    let format = match value_format(secret_id, tags.as_deref()) {
+        Ok(format) => format,
+        Err(err) => return Some(Finding::InvalidConfig { message: err.message() }),
+    };
+
+    let current = match current_secret(secret_id, format).await {
+        Ok(current) => current,
+        Err(err) => return Some(Finding::CheckFailed { message: err.message() }),
+    };
+
+    let config = match Config::new_for_secret(secret_id, tags, current.as_ref()) {
+        Ok(config) => config,
+        Err(err) => return Some(Finding::InvalidConfig { message: err.message() }),
+    };
+
+    match config.resource {
+        ResourceConfig::ServiceSpecificCredential(ref cfg) => {
+            match list_service_specific_credentials(Some(&cfg.user_name), Some(&cfg.service_name)).await {
+                Ok(creds) => check_credential(current.as_ref(), &creds),
+                Err(err) => Some(Finding::CheckFailed { message: format!("{}", err) }),
+            }
+        },
+    }
+}
+
+/// Checks the credential named by the AWSCURRENT value still exists and is active.
+fn check_credential(current: Option<&Secret>, creds: &[CredentialMetadata]) -> Option<Finding> {
+    let credential_id = match current.and_then(|secret| secret.service_specific_credential_id.as_ref()) {
+        Some(id) => id.to_string(),
+        None => return Some(Finding::NoCredential),
+    };
+
+    match creds.iter().find(|cred| cred.service_specific_credential_id == credential_id) {
+        None => Some(Finding::CredentialMissing { credential_id }),
+        Some(cred) if cred.status != CredentialStatus::Active => Some(Finding::CredentialInactive { credential_id }),
+        Some(_) => None,
+    }
+}
+
+/// Reports a rotation that is more than a day late.
+///
+/// A secret that has never been rotated is due one interval after it was created.
+/// Cron schedules aren't evaluated, so they're only checked if `AutomaticallyAfterDays` is also set.
+fn check_schedule(last_rotated: Option<DateTime<Utc>>, created: Option<DateTime<Utc>>, rules: Option<&RotationRulesType>, now: DateTime<Utc>) -> Option<Finding> {
+    let interval = rules.and_then(rotation_interval)?;
+    let due = last_rotated.or(created)? + interval;
+
+    if due + Duration::hours(GRACE_HOURS) < now {
+        Some(Finding::RotationOverdue {
+            last_rotated: last_rotated.map(format_time),
+            due: format_time(due),
+        })
+    } else {
+        None
+    }
+}
+
+/// The interval from `AutomaticallyAfterDays` or a `rate(...)` schedule expression.
+fn rotation_interval(rules: &RotationRulesType) -> Option<Duration> {
+    if let Some(rate) = rules.schedule_expression.as_deref()
+        .and_then(|expression| expression.strip_prefix("rate("))
+        .and_then(|expression| expression.strip_suffix(')')) {
+
+        let mut parts = rate.split_whitespace();
+        let value: i64 = parts.next()?.parse().ok()?;
+        return match parts.next()? {
+            "day" | "days" => Some(Duration::days(value)),
+            "hour" | "hours" => Some(Duration::hours(value)),
+            _ => None,
+        };
+    }
+
+    rules.automatically_after_days.map(Duration::days)
+}
+
+fn timestamp(date: &SdkDateTime) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(date.secs(), date.subsec_nanos()).single()
+}
+
+fn format_time(t: DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::metadata;
+
+    fn rules(days: Option<i64>, expression: Option<&str>) -> RotationRulesType {
+        RotationRulesType::builder()
+            .set_automatically_after_days(days)
+            .set_schedule_expression(expression.map(|s| s.to_string()))
+            .build()
+    }
+
+    #[test]
+    pub fn test_rotation_interval() {
+        assert_eq!(rotation_interval(&rules(Some(30), None)), Some(Duration::days(30)));
+        assert_eq!(rotation_interval(&rules(None, Some("rate(10 days)"))), Some(Duration::days(10)));
+        assert_eq!(rotation_interval(&rules(None, Some("rate(4 hours)"))), Some(Duration::hours(4)));
+        assert_eq!(rotation_interval(&rules(None, Some("cron(0 16 1,15 * ? *)"))), None);
+        assert_eq!(rotation_interval(&rules(Some(7), Some("cron(0 16 1,15 * ? *)"))), Some(Duration::days(7)));
+    }
+
+    #[test]
+    pub fn test_check_schedule() {
+        let now = Utc.with_ymd_and_hms(2026, 7, 15, 12, 0, 0).unwrap();
+        let rules = rules(Some(30), None);
+
+        let recent = Utc.with_ymd_and_hms(2026, 7, 1, 0, 0, 0).unwrap();
+        assert_eq!(check_schedule(Some(recent), None, Some(&rules), now), None);
+
+        let old = Utc.with_ymd_and_hms(2026, 5, 1, 0, 0, 0).unwrap();
+        assert_eq!(check_schedule(Some(old), None, Some(&rules), now), Some(Finding::RotationOverdue {
+            last_rotated: Some("2026-05-01T00:00:00Z".to_string()),
+            due: "2026-05-31T00:00:00Z".to_string(),
+        }));
+        assert_eq!(check_schedule(None, Some(old), Some(&rules), now), Some(Finding::RotationOverdue {
+            last_rotated: None,
+            due: "2026-05-31T00:00:00Z".to_string(),
+        }));
+        assert_eq!(check_schedule(Some(old), None, None, now), None);
+    }
+
+    #[test]
+    pub fn test_check_credential() {
+        let secret = Secret {
+            service_specific_credential_id: Some("A".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(check_credential(Some(&secret), &[metadata("A", CredentialStatus::Active)]), None);
+        assert_eq!(check_credential(Some(&secret), &[metadata("A", CredentialStatus::Inactive)]), Some(Finding::CredentialInactive {
+            credential_id: "A".to_string(),
+        }));
+        assert_eq!(check_credential(Some(&secret), &[metadata("B", CredentialStatus::Active)]), Some(Finding::CredentialMissing {
+            credential_id: "A".to_string(),
+        }));
+        assert_eq!(check_credential(None, &[]), Some(Finding::NoCredential));
+    }
+
+    #[test]
+    pub fn test_report_json() {
+        let report = SweepReport::new(vec![
+            SecretReport { secret_id: "b".to_string(), findings: vec![Finding::RotationDisabled] },
+            SecretReport { secret_id: "a".to_string(), findings: vec![] },
+        ]);
+
+        assert_eq!(serde_json::to_value(&report).unwrap(), serde_json::json!({
+            "checked": 2,
+            "failing": 1,
+            "secrets": [
+                {"secretId": "a", "findings": []},
+                {"secretId": "b", "findings": [{"check": "rotationDisabled"}]},
+            ],
+        }));
+    }
+}
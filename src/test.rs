@@ -1,14 +1,16 @@
-use std::time::Duration;
 use crate::{
     RotatorEvent,
     RotatorResult,
+    StepOutcome,
 };
-use crate::value::get_secret_value;
+use crate::config::Config;
+use crate::describe::describe_secret;
+use crate::replica::{ReplicaMode, Replication, wait_for_replicas};
+use crate::value::{get_secret_value, plan_read_pending};
+use crate::stage::AWSPENDING;
 
-pub fn test_secret(e: RotatorEvent) -> RotatorResult<()> {
-    let timeout = Duration::from_secs(2);
-
-    let _value = match get_secret_value(&e.secret_id, Some("AWSPENDING"), Some(&e.client_request_token), timeout) {
+pub async fn test_secret(e: RotatorEvent, config: &Config) -> RotatorResult<StepOutcome> {
+    let _value = match get_secret_value(&e.secret_id, config.value_format, Some(AWSPENDING), Some(&e.client_request_token)).await {
         Ok(value) => {
             info!("testSecret: Successfully retrieved secret for {}.", e.secret_id);
             Ok(value)
@@ -21,6 +23,31 @@ pub fn test_secret(e: RotatorEvent) -> RotatorResult<()> {
 
     // todo: test secret on resource
 
-    
-    Ok(())
-}
\ No newline at end of file
+    if config.replica_mode == ReplicaMode::Wait {
+        let regions = wait_for_replicas(&e.secret_id, &e.client_request_token).await?;
+        info!("testSecret: Replicas in [{}] have version {} of secret {}.", regions.join(", "), e.client_request_token, e.secret_id);
+    }
+
+    Ok(StepOutcome {
+        versions: vec![e.client_request_token],
+        ..Default::default()
+    })
+}
+
+pub async fn plan_test_secret(e: &RotatorEvent, config: &Config) -> RotatorResult<Vec<String>> {
+    let read = plan_read_pending(&e.secret_id, config.value_format, &e.client_request_token).await?;
+
+    let mut plan = vec![format!("{}, testSecret makes no other changes", read)];
+
+    if config.replica_mode == ReplicaMode::Wait {
+        let replication = Replication::from_secret(&describe_secret(&e.secret_id).await?);
+        let failed = replication.failed_regions();
+        if failed.is_empty() {
+            plan.push(format!("would wait until the replicas in [{}] have version {}", replication.replica_regions().join(", "), e.client_request_token));
+        } else {
+            plan.push(format!("would fail, since the replicas in [{}] failed and won't get version {}", failed.join(", "), e.client_request_token));
+        }
+    }
+
+    Ok(plan)
+}
@@ -0,0 +1,24 @@
+use crate::error::{RotatorResult, RotatorError};
+use crate::SM_CLIENT;
+use crate::metrics::time_api_call;
+
+/// Asks Secrets Manager to start a rotation now, rather than waiting for the schedule.
+pub async fn rotate_secret(secret_id: &str) -> RotatorResult<()> {
+    info!("starting rotation of secret {}", secret_id);
+
+    time_api_call("RotateSecret", SM_CLIENT.rotate_secret()
+        .secret_id(secret_id)
+        .rotate_immediately(true)
+        .send())
+        .await
+        .map_err(|e| match e.as_service_error() {
+            Some(err) if err.is_resource_not_found_exception() => {
+                RotatorError::SecretNotFound {
+                    secret_id: secret_id.to_string(),
+                    message: err.meta().message().unwrap_or_default().to_string(),
+                }
+            },
+            _ => RotatorError::from_sdk("RotateSecret", &e).unwrap_or_else(|| RotatorError::RotateSecret(format!("{:?}", e)))
+        })
+        .map(|_| ())
+}
@@ -0,0 +1,267 @@
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::fmt;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use crate::{RotatorError, RotatorEvent, RotatorResult};
+use crate::value::get_secret_value_string;
+use crate::stage::AWSPENDING;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleParseError(String);
+
+impl StdError for ScheduleParseError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for ScheduleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "schedule parse error: {}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Days {
+    Every,
+    Weekdays(Vec<Weekday>),
+    Dates(NaiveDate, NaiveDate),
+}
+
+impl Days {
+    fn contains(&self, date: NaiveDate) -> bool {
+        match self {
+            Days::Every => true,
+            Days::Weekdays(days) => days.contains(&date.weekday()),
+            Days::Dates(from, to) => *from <= date && date <= *to,
+        }
+    }
+}
+
+/// A recurring period such as `Mon-Fri 09:00-17:00 Europe/London`.
+#[derive(Debug, Clone, PartialEq)]
+struct Period {
+    days: Days,
+    times: Option<(NaiveTime, NaiveTime)>,
+    timezone: Tz,
+}
+
+impl Period {
+    fn contains(&self, now: DateTime<Utc>) -> bool {
+        let local = now.with_timezone(&self.timezone);
+        let (date, time) = (local.date_naive(), local.time());
+
+        match self.times {
+            None => self.days.contains(date),
+            Some((start, end)) if start <= end => self.days.contains(date) && start <= time && time < end,
+            // the range wraps past midnight and belongs to the day it starts on
+            Some((start, end)) => {
+                (self.days.contains(date) && start <= time)
+                    || (time < end && date.pred_opt().map(|yesterday| self.days.contains(yesterday)).unwrap_or(false))
+            },
+        }
+    }
+}
+
+impl TryFrom<&str> for Period {
+    type Error = ScheduleParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mut period = Period {
+            days: Days::Every,
+            times: None,
+            timezone: Tz::UTC,
+        };
+
+        for token in value.split_whitespace() {
+            if let Some(times) = parse_time_range(token) {
+                period.times = Some(times);
+            } else if let Some(days) = parse_days(token) {
+                period.days = days;
+            } else if let Ok(timezone) = token.parse::<Tz>() {
+                period.timezone = timezone;
+            } else {
+                return Err(ScheduleParseError(format!("invalid token '{}' in '{}'", token, value)));
+            }
+        }
+
+        Ok(period)
+    }
+}
+
+/// One or more periods separated by `;`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    periods: Vec<Period>,
+}
+
+impl Schedule {
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        self.periods.iter().any(|period| period.contains(now))
+    }
+}
+
+impl TryFrom<&str> for Schedule {
+    type Error = ScheduleParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let periods = value.split(';')
+            .map(|period| period.trim())
+            .filter(|period| !period.is_empty())
+            .map(Period::try_from)
+            .collect::<Result<Vec<Period>, ScheduleParseError>>()?;
+
+        if periods.is_empty() {
+            return Err(ScheduleParseError(format!("empty schedule '{}'", value)));
+        }
+
+        Ok(Schedule { periods })
+    }
+}
+
+/// When new credentials may be created.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RotationWindow {
+    pub allowed: Option<Schedule>,
+    pub blackout: Option<Schedule>,
+}
+
+impl RotationWindow {
+    /// Explains why rotation isn't allowed at `now`, or returns `None` if it is.
+    pub fn closed_reason(&self, now: DateTime<Utc>) -> Option<String> {
+        if let Some(ref blackout) = self.blackout {
+            if blackout.contains(now) {
+                return Some(format!("{} is in a rotation blackout", now));
+            }
+        }
+
+        if let Some(ref allowed) = self.allowed {
+            if !allowed.contains(now) {
+                return Some(format!("{} is outside the allowed rotation window", now));
+            }
+        }
+
+        None
+    }
+}
+
+/// Decides whether to decline createSecret because the window is closed.
+///
+/// Later steps aren't checked, so a rotation that has started can finish.
+/// A retried createSecret whose pending secret already exists isn't declined either,
+/// because its credential has already been changed.
+pub async fn check_window(e: &RotatorEvent, window: &RotationWindow, now: DateTime<Utc>) -> RotatorResult<Option<String>> {
+    let reason = match window.closed_reason(now) {
+        Some(reason) => reason,
+        None => return Ok(None),
+    };
+
+    match get_secret_value_string(&e.secret_id, Some(AWSPENDING), Some(&e.client_request_token)).await {
+        Ok(_) => {
+            info!("rotation window closed but version {} already has a pending secret: {}", e.client_request_token, reason);
+            Ok(None)
+        },
+        Err(RotatorError::SecretValueNotFound { .. }) => Ok(Some(reason)),
+        Err(err) => Err(err),
+    }
+}
+
+fn parse_time_range(token: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let mut parts = token.splitn(2, '-');
+    let start = NaiveTime::parse_from_str(parts.next()?, "%H:%M").ok()?;
+    let end = NaiveTime::parse_from_str(parts.next()?, "%H:%M").ok()?;
+
+    Some((start, end))
+}
+
+fn parse_days(token: &str) -> Option<Days> {
+    if token == "*" {
+        return Some(Days::Every);
+    }
+
+    if let Some((from, to)) = split_range(token, "..") {
+        let from = NaiveDate::parse_from_str(from, "%Y-%m-%d").ok()?;
+        let to = NaiveDate::parse_from_str(to, "%Y-%m-%d").ok()?;
+        return Some(Days::Dates(from, to));
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(token, "%Y-%m-%d") {
+        return Some(Days::Dates(date, date));
+    }
+
+    let mut days = vec![];
+    for part in token.split(',') {
+        match split_range(part, "-") {
+            Some((from, to)) => {
+                let (from, to) = (from.parse::<Weekday>().ok()?, to.parse::<Weekday>().ok()?);
+                let mut day = from;
+                days.push(day);
+                while day != to {
+                    day = day.succ();
+                    days.push(day);
+                }
+            },
+            None => days.push(part.parse::<Weekday>().ok()?),
+        }
+    }
+
+    Some(Days::Weekdays(days))
+}
+
+fn split_range<'a>(token: &'a str, separator: &str) -> Option<(&'a str, &'a str)> {
+    let index = token.find(separator)?;
+    Some((&token[..index], &token[index + separator.len()..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    pub fn test_weekday_window() {
+        let schedule = Schedule::try_from("Mon-Fri 09:00-17:00 Europe/London").expect("schedule parse error");
+
+        // Wednesday 2026-07-15, London is UTC+1 in summer
+        assert!(schedule.contains(at(2026, 7, 15, 8, 0)));
+        assert!(!schedule.contains(at(2026, 7, 15, 7, 59)));
+        assert!(!schedule.contains(at(2026, 7, 15, 16, 0)));
+        // Saturday
+        assert!(!schedule.contains(at(2026, 7, 18, 12, 0)));
+    }
+
+    #[test]
+    pub fn test_window_wrapping_midnight() {
+        let schedule = Schedule::try_from("Fri 22:00-02:00").expect("schedule parse error");
+
+        assert!(schedule.contains(at(2026, 7, 17, 23, 0)));
+        assert!(schedule.contains(at(2026, 7, 18, 1, 0)));
+        assert!(!schedule.contains(at(2026, 7, 18, 3, 0)));
+        assert!(!schedule.contains(at(2026, 7, 16, 23, 0)));
+    }
+
+    #[test]
+    pub fn test_blackout_overrides_window() {
+        let window = RotationWindow {
+            allowed: Some(Schedule::try_from("Mon,Tue,Wed,Thu,Fri").unwrap()),
+            blackout: Some(Schedule::try_from("2026-12-21..2027-01-03; Wed 12:00-14:00").unwrap()),
+        };
+
+        assert_eq!(window.closed_reason(at(2026, 7, 16, 12, 0)), None);
+        assert!(window.closed_reason(at(2026, 7, 15, 13, 0)).is_some());
+        assert!(window.closed_reason(at(2026, 12, 22, 12, 0)).is_some());
+        assert!(window.closed_reason(at(2026, 7, 18, 12, 0)).is_some());
+    }
+
+    #[test]
+    pub fn test_invalid_schedule() {
+        assert!(Schedule::try_from("Mon-Fri 9am").is_err());
+        assert!(Schedule::try_from("Mars/Olympus_Mons").is_err());
+        assert!(Schedule::try_from(" ; ").is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use std::path::Path;
+use serde_json::{json, Value};
+use rotator_harness::Harness;
+use rotator_harness::secretsmanager::{FakeValue, FakeVersion, AWSCURRENT, AWSPENDING, AWSPREVIOUS};
+
+const SERVICE_NAME: &str = "codecommit.amazonaws.com";
+
+fn start() -> Harness {
+    start_with(&[])
+}
+
+fn start_with(env: &[(&str, &str)]) -> Harness {
+    let mut function_env = vec![
+        ("ROTATOR_RESOURCE_TYPE", "ServiceSpecificCredential"),
+        ("ROTATOR_USER_NAME", "bob"),
+        ("ROTATOR_SERVICE_NAME", SERVICE_NAME),
+    ];
+    function_env.extend_from_slice(env);
+
+    let harness = Harness::start(Path::new(env!("CARGO_BIN_EXE_rotator")), &function_env)
+        .expect("failed to start the harness");
+
+    harness.iam.add_user("bob", "/");
+    harness
+}
+
+fn step(harness: &Harness, secret_id: &str, token: &str, step: &str) -> Value {
+    let result = harness.invoke(json!({"SecretId": secret_id, "ClientRequestToken": token, "Step": step}))
+        .unwrap_or_else(|| panic!("no result for {}, logs: {:#?}", step, harness.logs()));
+
+    match result.clone().response() {
+        Some(response) => response,
+        None => panic!("{} failed: {:?}, logs: {:#?}", step, result, harness.logs()),
+    }
+}
+
+#[test]
+pub fn test_health_check() {
+    let harness = start();
+
+    let response = harness.invoke(json!({"HealthCheck": true})).and_then(|result| result.response());
+
+    assert_eq!(response.map(|r| r["healthy"].clone()), Some(json!(true)), "logs: {:#?}", harness.logs());
+}
+
+#[test]
+pub fn test_rotation() {
+    let harness = start();
+    let arn = harness.secrets_manager.create_secret("db", Some("{}"), &[]);
+    harness.secrets_manager.start_rotation("db", "v2");
+
+    for name in ["createSecret", "setSecret", "testSecret", "finishSecret"].iter() {
+        step(&harness, &arn, "v2", name);
+    }
+
+    let credentials = harness.iam.credentials();
+    assert_eq!(credentials.len(), 1);
+
+    let secret = harness.secrets_manager.secret("db").unwrap();
+    assert_eq!(secret.version_with_stage(AWSCURRENT).map(|version| &version.id[..]), Some("v2"));
+    assert_eq!(secret.version_with_stage(AWSPREVIOUS).map(|version| &version.id[..]), Some("initial"));
+
+    let value: Value = serde_json::from_str(secret.string_value(AWSCURRENT).unwrap()).unwrap();
+    assert_eq!(value["password"], json!(credentials[0].password));
+    assert_eq!(value["service_specific_credential_id"], json!(credentials[0].id));
+    assert!(secret.tags.contains(&("rotator:pendingCredential".to_string(), "v2".to_string())));
+}
+
+#[test]
+pub fn test_binary_rotation() {
+    let harness = start();
+    let arn = harness.secrets_manager.create_secret("db", Some("unused"), &[("rotator:valueFormat", "Binary")]);
+    harness.secrets_manager.update_secret("db", |secret| secret.versions[0].value = Some(FakeValue::Binary("aHVudGVyMg==".to_string())));
+
+    for token in ["v2", "v3", "v4"].iter() {
+        harness.secrets_manager.start_rotation("db", token);
+        for name in ["createSecret", "setSecret", "testSecret", "finishSecret"].iter() {
+            step(&harness, &arn, token, name);
+        }
+    }
+
+    // the third rotation reset the credential the first one created
+    let credentials = harness.iam.credentials();
+    assert_eq!(credentials.len(), 2);
+    assert!(harness.iam.calls().contains(&"ResetServiceSpecificCredential".to_string()));
+
+    let secret = harness.secrets_manager.secret("db").unwrap();
+    assert_eq!(secret.version_with_stage(AWSCURRENT).map(|version| &version.id[..]), Some("v4"));
+    assert!(matches!(secret.version("v4").and_then(|version| version.value.as_ref()), Some(FakeValue::Binary(_))));
+
+    // v2 is AWSPREVIOUS when v4 is written, so it's still remembered
+    let tag = format!("{} v2={} v3={} v4={}", credentials[0].service_user_name, credentials[0].id, credentials[1].id, credentials[0].id);
+    assert!(secret.tags.contains(&("rotator:credentials".to_string(), tag)), "tags: {:?}", secret.tags);
+}
+
+#[test]
+pub fn test_audit_records() {
+    let path = std::env::temp_dir().join(format!("rotator-lambda-audit-{}.jsonl", std::process::id()));
+    let harness = start_with(&[("ROTATOR_AUDIT_FILE", &path.to_string_lossy()), ("ROTATOR_AUDIT_TAGS", "true")]);
+    let arn = harness.secrets_manager.create_secret("db", Some("{}"), &[]);
+    harness.secrets_manager.start_rotation("db", "v2");
+
+    for name in ["createSecret", "setSecret", "testSecret", "finishSecret"].iter() {
+        step(&harness, &arn, "v2", name);
+    }
+
+    let contents = std::fs::read_to_string(&path).expect("audit read error");
+    std::fs::remove_file(&path).ok();
+    let records: Vec<Value> = contents.lines().map(|line| serde_json::from_str(line).expect("json parse error")).collect();
+
+    let credential_id = harness.iam.credentials()[0].id.to_string();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["versionId"], json!("v2"));
+    assert_eq!(records[0]["previousVersionId"], json!("initial"));
+    assert_eq!(records[0]["changes"], json!([{"action": "created", "credentialId": credential_id}]));
+
+    let secret = harness.secrets_manager.secret("db").unwrap();
+    assert!(secret.tags.contains(&("rotator:lastRotationChanges".to_string(), format!("created:{}", credential_id))));
+    assert!(secret.tags.contains(&("rotator:lastRotationVersion".to_string(), "v2".to_string())));
+    let value: Value = serde_json::from_str(secret.string_value(AWSCURRENT).unwrap()).unwrap();
+    assert!(value.get("rotation_changes").is_none());
+}
+
+#[test]
+pub fn test_legacy_secret_with_two_credentials() {
+    let harness = start();
+    let older = harness.iam.add_credential("bob", SERVICE_NAME);
+    let current = harness.iam.add_credential("bob", SERVICE_NAME);
+
+    // written by a rotator that didn't record managed_credential_ids
+    let value = |id: &str| json!({"username": "bob-at-123456789012", "password": "pw", "service_specific_credential_id": id}).to_string();
+    let arn = harness.secrets_manager.create_secret("db", Some(&value(&current)), &[]);
+    harness.secrets_manager.update_secret("db", |secret| secret.versions.push(FakeVersion {
+        id: "older".to_string(),
+        stages: vec![AWSPREVIOUS.to_string()],
+        value: Some(FakeValue::String(value(&older))),
+        created_date: 0.0,
+    }));
+    harness.secrets_manager.start_rotation("db", "v2");
+
+    step(&harness, &arn, "v2", "createSecret");
+
+    assert!(harness.iam.calls().contains(&"ResetServiceSpecificCredential".to_string()));
+
+    let secret = harness.secrets_manager.secret("db").unwrap();
+    let pending: Value = serde_json::from_str(secret.string_value(AWSPENDING).unwrap()).unwrap();
+    assert_eq!(pending["service_specific_credential_id"], json!(older));
+    assert_eq!(pending["managed_credential_ids"], json!([older, current]));
+}
+
+#[test]
+pub fn test_retried_create_secret_manages_its_credential() {
+    let harness = start();
+    let current = harness.iam.add_credential("bob", SERVICE_NAME);
+
+    let value = json!({"username": "bob-at-123456789012", "password": "pw", "service_specific_credential_id": current, "managed_credential_ids": [current]});
+    let arn = harness.secrets_manager.create_secret("db", Some(&value.to_string()), &[]);
+    harness.secrets_manager.start_rotation("db", "v2");
+
+    // an earlier attempt created a credential, then failed before putting the pending value
+    let tag = format!("v2 {}", current);
+    harness.secrets_manager.update_secret("db", |secret| secret.tags.push(("rotator:pendingCredential".to_string(), tag)));
+    let created = harness.iam.add_credential("bob", SERVICE_NAME);
+
+    step(&harness, &arn, "v2", "createSecret");
+
+    let secret = harness.secrets_manager.secret("db").unwrap();
+    let pending: Value = serde_json::from_str(secret.string_value(AWSPENDING).unwrap()).unwrap();
+    assert_eq!(pending["service_specific_credential_id"], json!(created));
+    assert_eq!(pending["managed_credential_ids"], json!([current, created]));
+}
+
+#[test]
+pub fn test_retry_waits_for_lease_held_by_another_invocation() {
+    let harness = start_with(&[("ROTATOR_LEASE_TAGS", "true")]);
+    harness.secrets_manager.create_secret("db", Some("{}"), &[]);
+    harness.secrets_manager.start_rotation("db", "v2");
+
+    // the first attempt at the step is still running
+    let lease = "harness-earlier 2099-01-01T00:00:00Z v2".to_string();
+    harness.secrets_manager.update_secret("db", |secret| secret.tags.push(("rotator:lease".to_string(), lease.clone())));
+
+    let result = harness.invoke(json!({"SecretId": "db", "ClientRequestToken": "v2", "Step": "createSecret"}));
+
+    assert_eq!(result.as_ref().and_then(|result| result.error_type()), Some("LEASE_HELD"), "logs: {:#?}", harness.logs());
+    assert!(harness.iam.calls().is_empty());
+
+    let secret = harness.secrets_manager.secret("db").unwrap();
+    assert!(secret.tags.contains(&("rotator:lease".to_string(), lease)));
+}
+
+#[test]
+pub fn test_rollback_refused_by_policy() {
+    let harness = start_with(&[("ROTATOR_POLICY", r#"{"deniedUserNames": ["bob"]}"#)]);
+    let previous = harness.iam.add_credential("bob", SERVICE_NAME);
+    let current = harness.iam.add_credential("bob", SERVICE_NAME);
+
+    let value = |id: &str| json!({"username": "bob-at-123456789012", "password": "pw", "service_specific_credential_id": id, "managed_credential_ids": [previous, current]}).to_string();
+    let arn = harness.secrets_manager.create_secret("db", Some(&value(&current)), &[]);
+    harness.secrets_manager.update_secret("db", |secret| secret.versions.push(FakeVersion {
+        id: "older".to_string(),
+        stages: vec![AWSPREVIOUS.to_string()],
+        value: Some(FakeValue::String(value(&previous))),
+        created_date: 0.0,
+    }));
+
+    let result = harness.invoke(json!({"Command": "rollback", "SecretId": arn}));
+
+    assert_eq!(result.as_ref().and_then(|result| result.error_type()), Some("POLICY_VIOLATION"), "logs: {:#?}", harness.logs());
+    assert!(harness.iam.calls().is_empty());
+
+    let secret = harness.secrets_manager.secret("db").unwrap();
+    assert_eq!(secret.version_with_stage(AWSCURRENT).map(|version| &version.id[..]), Some("initial"));
+}
+
+#[test]
+pub fn test_rotation_not_enabled() {
+    let harness = start();
+    let arn = harness.secrets_manager.create_secret("db", Some("{}"), &[]);
+    harness.secrets_manager.start_rotation("db", "v2");
+    harness.secrets_manager.update_secret("db", |secret| secret.rotation_enabled = false);
+
+    let result = harness.invoke(json!({"SecretId": arn, "ClientRequestToken": "v2", "Step": "createSecret"}));
+
+    assert_eq!(result.as_ref().and_then(|result| result.error_type()), Some("ROTATION_NOT_ENABLED"), "logs: {:#?}", harness.logs());
+    assert!(harness.iam.calls().is_empty());
+}
+
+#[test]
+pub fn test_plan_without_pending_version() {
+    let harness = start();
+    let arn = harness.secrets_manager.create_secret("db", Some("{}"), &[]);
+
+    let response = harness.invoke(json!({"Command": "plan", "SecretId": arn}))
+        .and_then(|result| result.response())
+        .unwrap_or_else(|| panic!("plan failed, logs: {:#?}", harness.logs()));
+
+    let steps: Vec<&str> = response.as_array().unwrap().iter().map(|output| output["step"].as_str().unwrap()).collect();
+    assert_eq!(steps, vec!["createSecret", "setSecret", "testSecret", "finishSecret"]);
+
+    for output in response.as_array().unwrap()[1..3].iter() {
+        let plan = output["plan"].to_string();
+        assert!(plan.contains("no pending secret yet, would use the value created by createSecret"), "plan: {}", plan);
+    }
+    assert_eq!(harness.secrets_manager.calls().iter().filter(|call| *call == "PutSecretValue").count(), 0);
+    assert!(harness.iam.credentials().is_empty());
+}
+
+#[test]
+pub fn test_declined_rotation() {
+    let harness = start_with(&[("ROTATOR_NOTIFY_STDOUT", "true"), ("ROTATOR_LOG_FORMAT", "json")]);
+    let arn = harness.secrets_manager.create_secret("db", Some("{}"), &[("rotator:blackout", "*")]);
+    harness.secrets_manager.start_rotation("db", "v2");
+
+    let result = harness.invoke(json!({"SecretId": arn, "ClientRequestToken": "v2", "Step": "createSecret"}));
+
+    assert_eq!(result.as_ref().and_then(|result| result.error_type()), Some("ROTATION_DECLINED"), "logs: {:#?}", harness.logs());
+
+    let secret = harness.secrets_manager.secret("db").unwrap();
+    assert_eq!(secret.version_with_stage(AWSCURRENT).map(|version| &version.id[..]), Some("initial"));
+    assert_eq!(secret.version("v2").and_then(|version| version.value.as_ref()), None);
+    assert!(harness.iam.calls().is_empty());
+
+    let lines: Vec<Value> = harness.logs().iter()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let metrics: Vec<&Value> = lines.iter().filter(|line| line.get("Step").is_some() && line.get("Api").is_none()).collect();
+    assert_eq!(metrics.len(), 1, "logs: {:#?}", harness.logs());
+    assert_eq!(metrics[0]["Declined"], json!(1));
+    assert_eq!(metrics[0]["Failure"], json!(0));
+    assert!(!lines.iter().any(|line| line["outcome"] == json!("Failure")), "logs: {:#?}", harness.logs());
+    assert!(!lines.iter().any(|line| line["level"] == json!("ERROR") && line["target"].as_str().unwrap_or_default().starts_with("rotator")), "logs: {:#?}", harness.logs());
+}
+
+#[test]
+pub fn test_started_rotation_finishes_in_blackout() {
+    let harness = start();
+    let arn = harness.secrets_manager.create_secret("db", Some("{}"), &[]);
+    harness.secrets_manager.start_rotation("db", "v2");
+
+    step(&harness, &arn, "v2", "createSecret");
+    harness.secrets_manager.update_secret("db", |secret| secret.tags.push(("rotator:blackout".to_string(), "*".to_string())));
+
+    for name in ["setSecret", "testSecret", "finishSecret"].iter() {
+        step(&harness, &arn, "v2", name);
+    }
+
+    let secret = harness.secrets_manager.secret("db").unwrap();
+    assert_eq!(secret.version_with_stage(AWSCURRENT).map(|version| &version.id[..]), Some("v2"));
+}